@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE users ADD COLUMN password_changed_at TIMESTAMP;
\ No newline at end of file
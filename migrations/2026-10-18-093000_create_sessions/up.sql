@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE sessions (
+                       id TEXT PRIMARY KEY,
+                       user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                       created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+                       last_seen_at TIMESTAMP NOT NULL DEFAULT NOW(),
+                       user_agent TEXT,
+                       ip_address TEXT,
+                       revoked_at TIMESTAMP
+);
+
+CREATE INDEX sessions_user_id_idx ON sessions(user_id);
\ No newline at end of file
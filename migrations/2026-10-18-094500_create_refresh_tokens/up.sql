@@ -0,0 +1,11 @@
+-- Your SQL goes here
+CREATE TABLE refresh_tokens (
+                       id TEXT PRIMARY KEY,
+                       session_id TEXT NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+                       token_hash TEXT NOT NULL UNIQUE,
+                       created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+                       expires_at TIMESTAMP NOT NULL,
+                       used_at TIMESTAMP
+);
+
+CREATE INDEX refresh_tokens_session_id_idx ON refresh_tokens(session_id);
\ No newline at end of file
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+-- Existing accounts could already write posts, so they start out as authors.
+-- New registrations get the least-privileged role until an admin promotes them.
+ALTER TABLE users ADD COLUMN role VARCHAR NOT NULL DEFAULT 'author'
+    CHECK (role IN ('admin', 'editor', 'author', 'reader'));
+ALTER TABLE users ALTER COLUMN role SET DEFAULT 'reader';
\ No newline at end of file
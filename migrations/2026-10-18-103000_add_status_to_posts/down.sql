@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE posts ADD COLUMN published BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE posts SET published = TRUE WHERE status = 'published';
+
+DROP INDEX IF EXISTS posts_status_published_at_idx;
+ALTER TABLE posts DROP COLUMN IF EXISTS published_at;
+ALTER TABLE posts DROP COLUMN IF EXISTS status;
\ No newline at end of file
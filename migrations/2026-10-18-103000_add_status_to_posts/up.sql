@@ -0,0 +1,10 @@
+-- Your SQL goes here
+ALTER TABLE posts ADD COLUMN status VARCHAR NOT NULL DEFAULT 'draft'
+    CHECK (status IN ('draft', 'published', 'scheduled', 'archived'));
+ALTER TABLE posts ADD COLUMN published_at TIMESTAMP;
+
+UPDATE posts SET status = 'published', published_at = created_at WHERE published;
+
+ALTER TABLE posts DROP COLUMN published;
+
+CREATE INDEX posts_status_published_at_idx ON posts(status, published_at);
\ No newline at end of file
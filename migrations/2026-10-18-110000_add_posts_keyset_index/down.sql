@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS posts_created_at_id_idx;
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+CREATE INDEX posts_created_at_id_idx ON posts(created_at, id);
\ No newline at end of file
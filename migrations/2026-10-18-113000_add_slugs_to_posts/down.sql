@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS post_slug_history;
+ALTER TABLE posts DROP COLUMN IF EXISTS slug;
\ No newline at end of file
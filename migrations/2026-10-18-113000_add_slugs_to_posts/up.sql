@@ -0,0 +1,44 @@
+-- Your SQL goes here
+ALTER TABLE posts ADD COLUMN slug VARCHAR;
+
+-- Backfill from the title like `slugify`, numbering collisions in creation
+-- order. Each candidate is checked against every slug assigned so far, so a
+-- numbered slug can't clash with another post's title ("Hello" twice plus
+-- "Hello 2").
+DO $$
+DECLARE
+    post RECORD;
+    base VARCHAR;
+    candidate VARCHAR;
+    n INTEGER;
+BEGIN
+    FOR post IN SELECT id, title FROM posts ORDER BY created_at, id LOOP
+        base := COALESCE(
+            NULLIF(
+                rtrim(left(trim(both '-' from regexp_replace(lower(post.title), '[^a-z0-9]+', '-', 'g')), 80), '-'),
+                ''
+            ),
+            'post'
+        );
+        candidate := base;
+        n := 1;
+
+        WHILE EXISTS (SELECT 1 FROM posts WHERE slug = candidate) LOOP
+            n := n + 1;
+            candidate := base || '-' || n;
+        END LOOP;
+
+        UPDATE posts SET slug = candidate WHERE id = post.id;
+    END LOOP;
+END $$;
+
+ALTER TABLE posts ALTER COLUMN slug SET NOT NULL;
+ALTER TABLE posts ADD CONSTRAINT posts_slug_key UNIQUE (slug);
+
+CREATE TABLE post_slug_history (
+                       slug VARCHAR PRIMARY KEY,
+                       post_id TEXT NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+                       created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX post_slug_history_post_id_idx ON post_slug_history(post_id);
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE posts DROP COLUMN IF EXISTS rendered_body;
+ALTER TABLE posts DROP COLUMN IF EXISTS body_format;
\ No newline at end of file
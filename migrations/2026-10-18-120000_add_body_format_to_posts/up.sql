@@ -0,0 +1,8 @@
+-- Your SQL goes here
+-- Existing bodies were written as raw text; new posts default to markdown.
+ALTER TABLE posts ADD COLUMN body_format VARCHAR NOT NULL DEFAULT 'plain';
+ALTER TABLE posts ALTER COLUMN body_format SET DEFAULT 'markdown';
+ALTER TABLE posts ADD CONSTRAINT posts_body_format_check CHECK (body_format IN ('markdown', 'html', 'plain'));
+
+-- Rendered HTML cache, filled in by the server on the next read or write.
+ALTER TABLE posts ADD COLUMN rendered_body TEXT;
\ No newline at end of file
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS post_categories;
+DROP TABLE IF EXISTS post_tags;
+DROP TABLE IF EXISTS categories;
+DROP TABLE IF EXISTS tags;
\ No newline at end of file
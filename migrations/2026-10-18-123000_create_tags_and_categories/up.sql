@@ -0,0 +1,30 @@
+-- Your SQL goes here
+CREATE TABLE tags (
+                       id TEXT PRIMARY KEY,
+                       name VARCHAR NOT NULL UNIQUE,
+                       slug VARCHAR NOT NULL UNIQUE,
+                       created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE categories (
+                       id TEXT PRIMARY KEY,
+                       name VARCHAR NOT NULL UNIQUE,
+                       slug VARCHAR NOT NULL UNIQUE,
+                       created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE post_tags (
+                       post_id TEXT NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+                       tag_id TEXT NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+                       PRIMARY KEY (post_id, tag_id)
+);
+
+CREATE INDEX post_tags_tag_id_idx ON post_tags(tag_id);
+
+CREATE TABLE post_categories (
+                       post_id TEXT NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+                       category_id TEXT NOT NULL REFERENCES categories(id) ON DELETE CASCADE,
+                       PRIMARY KEY (post_id, category_id)
+);
+
+CREATE INDEX post_categories_category_id_idx ON post_categories(category_id);
\ No newline at end of file
@@ -0,0 +1,18 @@
+-- Your SQL goes here
+CREATE TABLE comments (
+                       id TEXT PRIMARY KEY,
+                       post_id TEXT NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+                       user_id TEXT REFERENCES users(id) ON DELETE SET NULL,
+                       guest_name VARCHAR,
+                       guest_email TEXT,
+                       parent_id TEXT REFERENCES comments(id) ON DELETE CASCADE,
+                       body TEXT NOT NULL,
+                       status VARCHAR NOT NULL DEFAULT 'pending'
+                           CHECK (status IN ('pending', 'approved', 'rejected', 'spam')),
+                       created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+                       updated_at TIMESTAMP
+);
+
+CREATE INDEX comments_post_id_created_at_idx ON comments(post_id, created_at);
+CREATE INDEX comments_parent_id_idx ON comments(parent_id);
+CREATE INDEX comments_status_idx ON comments(status);
\ No newline at end of file
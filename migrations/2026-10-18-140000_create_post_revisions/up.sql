@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE post_revisions (
+                       id TEXT PRIMARY KEY,
+                       post_id TEXT NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+                       editor_id TEXT REFERENCES users(id) ON DELETE SET NULL,
+                       title VARCHAR NOT NULL,
+                       body TEXT NOT NULL,
+                       body_format VARCHAR NOT NULL,
+                       created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX post_revisions_post_id_created_at_idx ON post_revisions(post_id, created_at);
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- Bumped on every change to a post; used as its ETag.
+ALTER TABLE posts ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
\ No newline at end of file
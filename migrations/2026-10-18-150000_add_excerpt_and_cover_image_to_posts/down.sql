@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE posts DROP COLUMN IF EXISTS cover_image_url;
+ALTER TABLE posts DROP COLUMN IF EXISTS excerpt;
\ No newline at end of file
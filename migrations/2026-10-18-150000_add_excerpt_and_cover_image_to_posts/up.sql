@@ -0,0 +1,4 @@
+-- Your SQL goes here
+-- Listings fall back to the start of the body when no excerpt is set.
+ALTER TABLE posts ADD COLUMN excerpt TEXT;
+ALTER TABLE posts ADD COLUMN cover_image_url TEXT;
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+-- Trashed posts keep their row until they are restored or purged.
+ALTER TABLE posts ADD COLUMN deleted_at TIMESTAMP;
+CREATE INDEX posts_deleted_at_idx ON posts (deleted_at) WHERE deleted_at IS NOT NULL;
\ No newline at end of file
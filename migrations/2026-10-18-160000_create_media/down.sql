@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE posts DROP COLUMN IF EXISTS cover_media_id;
+DROP TABLE IF EXISTS media;
\ No newline at end of file
@@ -0,0 +1,20 @@
+-- Your SQL goes here
+-- Files are stored under a key derived from their checksum, so one stored
+-- object can back several owners' media rows.
+CREATE TABLE media (
+                       id TEXT PRIMARY KEY,
+                       user_id TEXT REFERENCES users(id) ON DELETE SET NULL,
+                       storage_key TEXT NOT NULL,
+                       file_name VARCHAR NOT NULL,
+                       content_type VARCHAR NOT NULL,
+                       size_bytes BIGINT NOT NULL,
+                       width INTEGER,
+                       height INTEGER,
+                       checksum VARCHAR NOT NULL,
+                       created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+                       UNIQUE (user_id, checksum)
+);
+
+CREATE INDEX media_storage_key_idx ON media(storage_key);
+
+ALTER TABLE posts ADD COLUMN cover_media_id TEXT REFERENCES media(id) ON DELETE SET NULL;
\ No newline at end of file
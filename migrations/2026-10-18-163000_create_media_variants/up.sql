@@ -0,0 +1,15 @@
+-- Your SQL goes here
+CREATE TABLE media_variants (
+                       id TEXT PRIMARY KEY,
+                       media_id TEXT NOT NULL REFERENCES media(id) ON DELETE CASCADE,
+                       name VARCHAR NOT NULL,
+                       content_type VARCHAR NOT NULL,
+                       storage_key TEXT NOT NULL,
+                       width INTEGER NOT NULL,
+                       height INTEGER NOT NULL,
+                       size_bytes BIGINT NOT NULL,
+                       created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+                       UNIQUE (media_id, name, content_type)
+);
+
+CREATE INDEX media_variants_storage_key_idx ON media_variants(storage_key);
\ No newline at end of file
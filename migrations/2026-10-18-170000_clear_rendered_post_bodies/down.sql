@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+-- Nothing to undo: the render backfill task fills the cache in again.
+SELECT 1;
\ No newline at end of file
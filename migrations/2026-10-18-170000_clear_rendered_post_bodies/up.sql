@@ -0,0 +1,6 @@
+-- Your SQL goes here
+-- Cached HTML predates prefixing ids; the render backfill task fills it in again.
+-- The trigger is off so clearing the cache keeps the posts' edit times.
+ALTER TABLE posts DISABLE TRIGGER set_updated_at;
+UPDATE posts SET rendered_body = NULL;
+ALTER TABLE posts ENABLE TRIGGER set_updated_at;
\ No newline at end of file
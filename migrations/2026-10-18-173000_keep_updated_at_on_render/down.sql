@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER set_updated_at ON posts;
+SELECT diesel_manage_updated_at('posts');
+DROP FUNCTION IF EXISTS posts_set_updated_at();
\ No newline at end of file
@@ -0,0 +1,19 @@
+-- Your SQL goes here
+-- Like diesel_set_updated_at, but filling in the rendered HTML cache isn't an
+-- edit. search_vector is generated, so it isn't computed yet in BEFORE triggers.
+CREATE OR REPLACE FUNCTION posts_set_updated_at() RETURNS trigger AS $$
+BEGIN
+    IF (
+        to_jsonb(NEW) - 'rendered_body' - 'search_vector' - 'updated_at'
+            IS DISTINCT FROM to_jsonb(OLD) - 'rendered_body' - 'search_vector' - 'updated_at' AND
+        NEW.updated_at IS NOT DISTINCT FROM OLD.updated_at
+    ) THEN
+        NEW.updated_at := current_timestamp;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER set_updated_at ON posts;
+CREATE TRIGGER set_updated_at BEFORE UPDATE ON posts
+    FOR EACH ROW EXECUTE PROCEDURE posts_set_updated_at();
\ No newline at end of file
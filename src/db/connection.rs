@@ -1,16 +1,128 @@
+use actix_web::web;
 use diesel::pg::PgConnection;
-use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::r2d2::{ConnectionManager, Pool, PooledConnection};
+use diesel::QueryResult;
+use std::{env, sync::Arc, time::Duration};
+
+use crate::{
+    errors::ApiError,
+    repositories::{PostRepository, UserRepository},
+    storage::Storage,
+    utils::images::VariantSpec,
+};
 
 pub type DbPool = Pool<ConnectionManager<PgConnection>>;
 
+const DEFAULT_POOL_MAX_SIZE: u32 = 10;
+const DEFAULT_POOL_TIMEOUT_SECS: u64 = 30;
+
 #[derive(Clone)]
 pub struct AppState {
-    pub pool: DbPool,
+    /// `None` when the state is built from in-memory repositories alone, as
+    /// in tests. Post, user and session handlers only go through the
+    /// repositories; comments, media, revisions, taxonomy, feeds, the sitemap
+    /// and search still query Postgres directly and fail without a pool.
+    pub pool: Option<DbPool>,
+    pub posts: Arc<dyn PostRepository>,
+    pub users: Arc<dyn UserRepository>,
+    pub storage: Arc<dyn Storage>,
+    pub image_variants: Arc<[VariantSpec]>,
+}
+
+impl AppState {
+    pub fn pool(&self) -> Result<&DbPool, ApiError> {
+        self.pool
+            .as_ref()
+            .ok_or_else(|| ApiError::Internal("No database pool is configured".into()))
+    }
+
+    pub fn conn(&self) -> Result<PooledConnection<ConnectionManager<PgConnection>>, ApiError> {
+        Ok(self.pool()?.get()?)
+    }
+
+    /// Runs `f` on actix's blocking thread pool. Diesel queries, repository
+    /// calls and password hashing all block, and running them on the async
+    /// worker would stall every other request it is serving.
+    pub async fn blocking<T, F>(&self, f: F) -> Result<T, ApiError>
+    where
+        F: FnOnce(&AppState) -> Result<T, ApiError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let state = self.clone();
+        web::block(move || f(&state)).await?
+    }
+
+    /// `blocking` for work that only needs a pooled connection.
+    pub async fn with_conn<T, F>(&self, f: F) -> Result<T, ApiError>
+    where
+        F: FnOnce(&mut PgConnection) -> QueryResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.blocking(move |data| {
+            let mut conn = data.conn()?;
+            Ok(f(&mut conn)?)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+impl AppState {
+    /// State backed only by in-memory repositories, for handler tests.
+    pub fn in_memory(posts: Arc<dyn PostRepository>, users: Arc<dyn UserRepository>) -> Self {
+        let root = env::temp_dir().join(format!("app-state-{}", uuid::Uuid::new_v4()));
+        AppState {
+            pool: None,
+            posts,
+            users,
+            storage: Arc::new(crate::storage::local::LocalStorage::new(
+                root,
+                "/media/files/",
+            )),
+            image_variants: Arc::from(Vec::new()),
+        }
+    }
+}
+
+/// Pool sizing, read from `DATABASE_POOL_MAX_SIZE`, `DATABASE_POOL_MIN_IDLE`
+/// and `DATABASE_POOL_TIMEOUT_SECS`.
+pub struct PoolConfig {
+    pub max_size: u32,
+    /// Idle connections kept open; `None` keeps up to `max_size`.
+    pub min_idle: Option<u32>,
+    /// How long a checkout waits for a free connection before failing.
+    pub connection_timeout: Duration,
+}
+
+impl PoolConfig {
+    pub fn from_env() -> Self {
+        PoolConfig {
+            max_size: match env::var("DATABASE_POOL_MAX_SIZE") {
+                Ok(size) => size
+                    .parse()
+                    .expect("DATABASE_POOL_MAX_SIZE must be a whole number"),
+                Err(_) => DEFAULT_POOL_MAX_SIZE,
+            },
+            min_idle: env::var("DATABASE_POOL_MIN_IDLE").ok().map(|idle| {
+                idle.parse()
+                    .expect("DATABASE_POOL_MIN_IDLE must be a whole number")
+            }),
+            connection_timeout: Duration::from_secs(match env::var("DATABASE_POOL_TIMEOUT_SECS") {
+                Ok(secs) => secs
+                    .parse()
+                    .expect("DATABASE_POOL_TIMEOUT_SECS must be a whole number of seconds"),
+                Err(_) => DEFAULT_POOL_TIMEOUT_SECS,
+            }),
+        }
+    }
 }
 
-pub fn establish_pool(database_url: String) -> DbPool {
+pub fn establish_pool(database_url: String, config: PoolConfig) -> DbPool {
     let manager = ConnectionManager::<PgConnection>::new(database_url);
     Pool::builder()
+        .max_size(config.max_size)
+        .min_idle(config.min_idle)
+        .connection_timeout(config.connection_timeout)
         .build(manager)
         .expect("Failed to create pool.")
 }
@@ -4,40 +4,212 @@
 #![allow(clippy::all)]
 
 use crate::{
-    db::schema::{posts, users},
-    utils::hashing::hash_password,
+    db::schema::{
+        categories, comments, media, media_variants, post_revisions, posts, refresh_tokens,
+        sessions, tags, users,
+    },
+    utils::{hashing::hash_password, render::render_body},
 };
-use chrono::NaiveDateTime;
+use chrono::{NaiveDateTime, Utc};
 use diesel::{
+    deserialize::{self, FromSql, FromSqlRow},
+    expression::AsExpression,
+    pg::{Pg, PgValue},
+    serialize::{self, IsNull, Output, ToSql},
+    sql_types::Text,
     AsChangeset, Insertable, QueryDsl, Queryable, RunQueryDsl, Selectable, SelectableHelper,
 };
 use serde::{Deserialize, Serialize};
+use std::{io::Write, str::FromStr};
 use uuid::Uuid;
 
-#[derive(Queryable, Selectable, Serialize, Debug)]
+#[derive(Queryable, Selectable, Serialize, Debug, Clone)]
 #[diesel(table_name = crate::db::schema::posts)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct Post {
     pub id: String,
     pub title: String,
     pub body: String,
-    pub published: bool,
     pub user_id: Option<String>,
     pub created_at: NaiveDateTime,
     pub updated_at: Option<NaiveDateTime>,
+    pub status: PostStatus,
+    pub published_at: Option<NaiveDateTime>,
+    pub slug: String,
+    pub body_format: BodyFormat,
+    pub rendered_body: Option<String>,
+    pub version: i32,
+    pub excerpt: Option<String>,
+    pub cover_image_url: Option<String>,
+    pub deleted_at: Option<NaiveDateTime>,
+    pub cover_media_id: Option<String>,
 }
 
-#[derive(Queryable, Selectable, Serialize, Debug)]
+impl Post {
+    pub fn is_public(&self) -> bool {
+        self.status == PostStatus::Published
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum PostStatus {
+    Draft,
+    Published,
+    Scheduled,
+    Archived,
+}
+
+impl PostStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PostStatus::Draft => "draft",
+            PostStatus::Published => "published",
+            PostStatus::Scheduled => "scheduled",
+            PostStatus::Archived => "archived",
+        }
+    }
+}
+
+impl FromStr for PostStatus {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "draft" => Ok(PostStatus::Draft),
+            "published" => Ok(PostStatus::Published),
+            "scheduled" => Ok(PostStatus::Scheduled),
+            "archived" => Ok(PostStatus::Archived),
+            other => Err(format!("Unknown post status: {}", other)),
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for PostStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Pg> for PostStatus {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        let value = <String as FromSql<Text, Pg>>::from_sql(bytes)?;
+        value.parse().map_err(Into::into)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum BodyFormat {
+    Markdown,
+    Html,
+    Plain,
+}
+
+impl BodyFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BodyFormat::Markdown => "markdown",
+            BodyFormat::Html => "html",
+            BodyFormat::Plain => "plain",
+        }
+    }
+}
+
+impl FromStr for BodyFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "markdown" => Ok(BodyFormat::Markdown),
+            "html" => Ok(BodyFormat::Html),
+            "plain" => Ok(BodyFormat::Plain),
+            other => Err(format!("Unknown body format: {}", other)),
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for BodyFormat {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Pg> for BodyFormat {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        let value = <String as FromSql<Text, Pg>>::from_sql(bytes)?;
+        value.parse().map_err(Into::into)
+    }
+}
+
+#[derive(Queryable, Selectable, Serialize, Debug, Clone)]
 #[diesel(table_name = crate::db::schema::users)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct User {
     pub id: String,
     pub name: String,
     pub email: String,
+    #[serde(skip_serializing)]
     pub password: Option<String>,
     pub verified: bool,
     pub created_at: NaiveDateTime,
     pub updated_at: Option<NaiveDateTime>,
+    pub verification_sent_at: Option<NaiveDateTime>,
+    pub password_changed_at: Option<NaiveDateTime>,
+    pub role: Role,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Admin,
+    Editor,
+    Author,
+    Reader,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Editor => "editor",
+            Role::Author => "author",
+            Role::Reader => "reader",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "admin" => Ok(Role::Admin),
+            "editor" => Ok(Role::Editor),
+            "author" => Ok(Role::Author),
+            "reader" => Ok(Role::Reader),
+            other => Err(format!("Unknown role: {}", other)),
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for Role {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Pg> for Role {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        let value = <String as FromSql<Text, Pg>>::from_sql(bytes)?;
+        value.parse().map_err(Into::into)
+    }
 }
 
 #[derive(Insertable, Serialize, Deserialize)]
@@ -47,19 +219,22 @@ pub struct CreateUser {
     pub name: String,
     pub email: String,
     pub password: String,
+    pub verification_sent_at: Option<NaiveDateTime>,
 }
 
 impl CreateUser {
-    pub fn new(name: String, email: String, password: String) -> Result<Self, String> {
-        match hash_password(password) {
-            Ok(hashed_password) => Ok(CreateUser {
-                id: Uuid::new_v4().to_string(),
-                name,
-                email,
-                password: hashed_password,
-            }),
-            Err(e) => Err(format!("Error hashing password: {}", e).into()),
-        }
+    pub fn new(
+        name: String,
+        email: String,
+        password: String,
+    ) -> Result<Self, argon2::password_hash::Error> {
+        Ok(CreateUser {
+            id: Uuid::new_v4().to_string(),
+            name,
+            email,
+            password: hash_password(password)?,
+            verification_sent_at: Some(Utc::now().naive_utc()),
+        })
     }
 }
 
@@ -70,15 +245,431 @@ pub struct CreatePost {
     pub title: String,
     pub body: String,
     pub user_id: String,
+    pub slug: String,
+    pub body_format: BodyFormat,
+    pub rendered_body: Option<String>,
 }
 
 impl CreatePost {
-    pub fn new(title: String, body: String, user_id: String) -> Self {
+    pub fn new(
+        title: String,
+        body: String,
+        body_format: BodyFormat,
+        user_id: String,
+        slug: String,
+    ) -> Self {
         CreatePost {
             id: Uuid::new_v4().to_string(),
             title,
+            rendered_body: Some(render_body(&body, body_format)),
             body,
             user_id,
+            slug,
+            body_format,
+        }
+    }
+}
+
+/// Changes for a partial post update; `None` leaves a column untouched and
+/// `Some(None)` clears a nullable one.
+#[derive(AsChangeset, Default, Debug)]
+#[diesel(table_name = posts)]
+pub struct UpdatePost {
+    pub title: Option<String>,
+    pub body: Option<String>,
+    pub body_format: Option<BodyFormat>,
+    pub rendered_body: Option<Option<String>>,
+    pub slug: Option<String>,
+    pub status: Option<PostStatus>,
+    pub published_at: Option<Option<NaiveDateTime>>,
+    pub excerpt: Option<Option<String>>,
+    pub cover_image_url: Option<Option<String>>,
+    pub cover_media_id: Option<Option<String>>,
+}
+
+#[derive(Queryable, Selectable, Serialize, Clone, Debug)]
+#[diesel(table_name = crate::db::schema::sessions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Session {
+    pub id: String,
+    pub user_id: String,
+    pub created_at: NaiveDateTime,
+    pub last_seen_at: NaiveDateTime,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable, Serialize, Deserialize)]
+#[diesel(table_name = sessions)]
+pub struct CreateSession {
+    pub id: String,
+    pub user_id: String,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+impl CreateSession {
+    pub fn new(user_id: String, user_agent: Option<String>, ip_address: Option<String>) -> Self {
+        CreateSession {
+            id: Uuid::new_v4().to_string(),
+            user_id,
+            user_agent,
+            ip_address,
+        }
+    }
+}
+
+#[derive(Queryable, Selectable, Serialize, Clone, Debug)]
+#[diesel(table_name = crate::db::schema::refresh_tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct RefreshToken {
+    pub id: String,
+    pub session_id: String,
+    pub token_hash: String,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable, Serialize, Deserialize)]
+#[diesel(table_name = refresh_tokens)]
+pub struct CreateRefreshToken {
+    pub id: String,
+    pub session_id: String,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+}
+
+impl CreateRefreshToken {
+    pub fn new(session_id: String, token_hash: String, expires_at: NaiveDateTime) -> Self {
+        CreateRefreshToken {
+            id: Uuid::new_v4().to_string(),
+            session_id,
+            token_hash,
+            expires_at,
+        }
+    }
+}
+
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = crate::db::schema::tags)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Tag {
+    pub id: String,
+    pub name: String,
+    pub slug: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Serialize, Deserialize)]
+#[diesel(table_name = tags)]
+pub struct CreateTag {
+    pub id: String,
+    pub name: String,
+    pub slug: String,
+}
+
+impl CreateTag {
+    pub fn new(name: String, slug: String) -> Self {
+        CreateTag {
+            id: Uuid::new_v4().to_string(),
+            name,
+            slug,
+        }
+    }
+}
+
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = crate::db::schema::categories)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Category {
+    pub id: String,
+    pub name: String,
+    pub slug: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Serialize, Deserialize)]
+#[diesel(table_name = categories)]
+pub struct CreateCategory {
+    pub id: String,
+    pub name: String,
+    pub slug: String,
+}
+
+impl CreateCategory {
+    pub fn new(name: String, slug: String) -> Self {
+        CreateCategory {
+            id: Uuid::new_v4().to_string(),
+            name,
+            slug,
+        }
+    }
+}
+
+/// A tag or category as attached to a post.
+#[derive(Serialize, Debug, Clone)]
+pub struct Term {
+    pub name: String,
+    pub slug: String,
+}
+
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = crate::db::schema::comments)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Comment {
+    pub id: String,
+    pub post_id: String,
+    pub user_id: Option<String>,
+    pub guest_name: Option<String>,
+    #[serde(skip_serializing)]
+    pub guest_email: Option<String>,
+    pub parent_id: Option<String>,
+    pub body: String,
+    pub status: CommentStatus,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum CommentStatus {
+    Pending,
+    Approved,
+    Rejected,
+    Spam,
+}
+
+impl CommentStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CommentStatus::Pending => "pending",
+            CommentStatus::Approved => "approved",
+            CommentStatus::Rejected => "rejected",
+            CommentStatus::Spam => "spam",
+        }
+    }
+}
+
+impl FromStr for CommentStatus {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "pending" => Ok(CommentStatus::Pending),
+            "approved" => Ok(CommentStatus::Approved),
+            "rejected" => Ok(CommentStatus::Rejected),
+            "spam" => Ok(CommentStatus::Spam),
+            other => Err(format!("Unknown comment status: {}", other)),
+        }
+    }
+}
+
+impl ToSql<Text, Pg> for CommentStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Pg> for CommentStatus {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        let value = <String as FromSql<Text, Pg>>::from_sql(bytes)?;
+        value.parse().map_err(Into::into)
+    }
+}
+
+#[derive(Insertable, Serialize, Deserialize)]
+#[diesel(table_name = comments)]
+pub struct CreateComment {
+    pub id: String,
+    pub post_id: String,
+    pub user_id: Option<String>,
+    pub guest_name: Option<String>,
+    pub guest_email: Option<String>,
+    pub parent_id: Option<String>,
+    pub body: String,
+    pub status: CommentStatus,
+}
+
+impl CreateComment {
+    pub fn new(
+        post_id: String,
+        user_id: Option<String>,
+        guest_name: Option<String>,
+        guest_email: Option<String>,
+        parent_id: Option<String>,
+        body: String,
+        status: CommentStatus,
+    ) -> Self {
+        CreateComment {
+            id: Uuid::new_v4().to_string(),
+            post_id,
+            user_id,
+            guest_name,
+            guest_email,
+            parent_id,
+            body,
+            status,
+        }
+    }
+}
+
+/// A previous version of a post's content, captured when it was overwritten.
+/// `editor_id` is the user whose change replaced it.
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = crate::db::schema::post_revisions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct PostRevision {
+    pub id: String,
+    pub post_id: String,
+    pub editor_id: Option<String>,
+    pub title: String,
+    pub body: String,
+    pub body_format: BodyFormat,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Serialize, Deserialize)]
+#[diesel(table_name = post_revisions)]
+pub struct CreatePostRevision {
+    pub id: String,
+    pub post_id: String,
+    pub editor_id: Option<String>,
+    pub title: String,
+    pub body: String,
+    pub body_format: BodyFormat,
+}
+
+impl CreatePostRevision {
+    pub fn new(post: &Post, editor_id: String) -> Self {
+        CreatePostRevision {
+            id: Uuid::new_v4().to_string(),
+            post_id: post.id.clone(),
+            editor_id: Some(editor_id),
+            title: post.title.clone(),
+            body: post.body.clone(),
+            body_format: post.body_format,
+        }
+    }
+}
+
+/// An uploaded file. `storage_key` is derived from the checksum, so several
+/// rows can share one stored object.
+#[derive(Queryable, Selectable, Serialize, Clone, Debug)]
+#[diesel(table_name = crate::db::schema::media)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Media {
+    pub id: String,
+    pub user_id: Option<String>,
+    #[serde(skip_serializing)]
+    pub storage_key: String,
+    pub file_name: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub checksum: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl Media {
+    pub fn is_image(&self) -> bool {
+        self.content_type.starts_with("image/")
+    }
+}
+
+#[derive(Insertable, Serialize, Deserialize)]
+#[diesel(table_name = media)]
+pub struct CreateMedia {
+    pub id: String,
+    pub user_id: Option<String>,
+    pub storage_key: String,
+    pub file_name: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub checksum: String,
+}
+
+impl CreateMedia {
+    pub fn new(
+        user_id: String,
+        storage_key: String,
+        file_name: String,
+        content_type: String,
+        size_bytes: i64,
+        dimensions: Option<(i32, i32)>,
+        checksum: String,
+    ) -> Self {
+        CreateMedia {
+            id: Uuid::new_v4().to_string(),
+            user_id: Some(user_id),
+            storage_key,
+            file_name,
+            content_type,
+            size_bytes,
+            width: dimensions.map(|(width, _)| width),
+            height: dimensions.map(|(_, height)| height),
+            checksum,
+        }
+    }
+}
+
+/// A resized rendition of an image upload.
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = crate::db::schema::media_variants)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct MediaVariant {
+    #[serde(skip_serializing)]
+    pub id: String,
+    #[serde(skip_serializing)]
+    pub media_id: String,
+    pub name: String,
+    pub content_type: String,
+    #[serde(skip_serializing)]
+    pub storage_key: String,
+    pub width: i32,
+    pub height: i32,
+    pub size_bytes: i64,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Serialize, Deserialize)]
+#[diesel(table_name = media_variants)]
+pub struct CreateMediaVariant {
+    pub id: String,
+    pub media_id: String,
+    pub name: String,
+    pub content_type: String,
+    pub storage_key: String,
+    pub width: i32,
+    pub height: i32,
+    pub size_bytes: i64,
+}
+
+impl CreateMediaVariant {
+    pub fn new(
+        media_id: String,
+        name: String,
+        content_type: String,
+        storage_key: String,
+        width: i32,
+        height: i32,
+        size_bytes: i64,
+    ) -> Self {
+        CreateMediaVariant {
+            id: Uuid::new_v4().to_string(),
+            media_id,
+            name,
+            content_type,
+            storage_key,
+            width,
+            height,
+            size_bytes,
         }
     }
 }
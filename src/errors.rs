@@ -0,0 +1,232 @@
+use actix_web::{error::BlockingError, http::StatusCode, HttpResponse, ResponseError};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use jsonwebtoken::errors::ErrorKind;
+use serde::Serialize;
+use std::fmt;
+use validator::ValidationErrors;
+
+use crate::repositories::RepositoryError;
+
+/// The error type handlers return. Every variant renders as the same JSON
+/// shape:
+///
+/// ```json
+/// {"error": {"code": "not_found", "message": "...", "fields": [], "request_id": "..."}}
+/// ```
+///
+/// `request_id` is filled in by the `RequestId` middleware and is `null`
+/// outside of it.
+#[derive(Debug)]
+pub enum ApiError {
+    Validation(Vec<FieldError>),
+    BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    Gone(String),
+    PreconditionFailed(String),
+    PayloadTooLarge(String),
+    UnsupportedMediaType(String),
+    TooManyRequests(String),
+    ServiceUnavailable(String),
+    /// The detail is logged but never sent to the client.
+    Internal(String),
+}
+
+/// One failed check on a request field.
+#[derive(Serialize, Debug, Clone)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: ErrorDetail<'a>,
+}
+
+#[derive(Serialize)]
+struct ErrorDetail<'a> {
+    code: &'static str,
+    message: &'a str,
+    fields: &'a [FieldError],
+    request_id: Option<&'a str>,
+}
+
+impl ApiError {
+    /// Machine-readable name of the error, stable across message changes.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::Validation(_) => "validation_failed",
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::Gone(_) => "gone",
+            ApiError::PreconditionFailed(_) => "precondition_failed",
+            ApiError::PayloadTooLarge(_) => "payload_too_large",
+            ApiError::UnsupportedMediaType(_) => "unsupported_media_type",
+            ApiError::TooManyRequests(_) => "rate_limited",
+            ApiError::ServiceUnavailable(_) => "service_unavailable",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    /// What the client is told.
+    pub fn message(&self) -> &str {
+        match self {
+            ApiError::Validation(_) => "Validation error(s)",
+            ApiError::Internal(_) => "An unexpected error occurred",
+            ApiError::BadRequest(message)
+            | ApiError::Unauthorized(message)
+            | ApiError::Forbidden(message)
+            | ApiError::NotFound(message)
+            | ApiError::Conflict(message)
+            | ApiError::Gone(message)
+            | ApiError::PreconditionFailed(message)
+            | ApiError::PayloadTooLarge(message)
+            | ApiError::UnsupportedMediaType(message)
+            | ApiError::TooManyRequests(message)
+            | ApiError::ServiceUnavailable(message) => message,
+        }
+    }
+
+    /// Builds the response, tagged with the id of the request it answers.
+    pub fn render(&self, request_id: Option<&str>) -> HttpResponse {
+        let fields = match self {
+            ApiError::Validation(fields) => fields.as_slice(),
+            _ => &[],
+        };
+
+        HttpResponse::build(self.status_code()).json(ErrorBody {
+            error: ErrorDetail {
+                code: self.code(),
+                message: self.message(),
+                fields,
+                request_id,
+            },
+        })
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Internal(detail) => write!(f, "{}", detail),
+            _ => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::Validation(_) | ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Gone(_) => StatusCode::GONE,
+            ApiError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ApiError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        self.render(None)
+    }
+}
+
+impl From<DieselError> for ApiError {
+    fn from(error: DieselError) -> Self {
+        match error {
+            DieselError::NotFound => ApiError::NotFound("Resource not found".into()),
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                ApiError::Conflict("Resource already exists".into())
+            }
+            DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _) => {
+                ApiError::Conflict("Resource is referenced by or refers to a missing record".into())
+            }
+            error => ApiError::Internal(format!("Database error: {}", error)),
+        }
+    }
+}
+
+impl From<RepositoryError> for ApiError {
+    fn from(error: RepositoryError) -> Self {
+        match error {
+            RepositoryError::Conflict(message) => ApiError::Conflict(message),
+            RepositoryError::Unavailable => {
+                ApiError::ServiceUnavailable("The database is unavailable, please try again".into())
+            }
+            RepositoryError::Backend(detail) => ApiError::Internal(detail),
+        }
+    }
+}
+
+impl From<diesel::r2d2::PoolError> for ApiError {
+    fn from(_: diesel::r2d2::PoolError) -> Self {
+        ApiError::ServiceUnavailable("The database is unavailable, please try again".into())
+    }
+}
+
+impl From<BlockingError> for ApiError {
+    fn from(_: BlockingError) -> Self {
+        ApiError::Internal("Blocking task was cancelled or panicked".into())
+    }
+}
+
+impl From<argon2::password_hash::Error> for ApiError {
+    fn from(error: argon2::password_hash::Error) -> Self {
+        match error {
+            argon2::password_hash::Error::Password => {
+                ApiError::Unauthorized("Invalid credentials".into())
+            }
+            error => ApiError::Internal(format!("Password hashing error: {}", error)),
+        }
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for ApiError {
+    fn from(error: jsonwebtoken::errors::Error) -> Self {
+        match error.kind() {
+            ErrorKind::ExpiredSignature => ApiError::Unauthorized("Token has expired".into()),
+            // Failing to sign or to load the key is our fault, not the client's.
+            ErrorKind::InvalidEcdsaKey
+            | ErrorKind::InvalidRsaKey(_)
+            | ErrorKind::RsaFailedSigning
+            | ErrorKind::InvalidKeyFormat
+            | ErrorKind::Crypto(_) => ApiError::Internal(format!("Token error: {}", error)),
+            _ => ApiError::Unauthorized("Invalid token".into()),
+        }
+    }
+}
+
+impl From<ValidationErrors> for ApiError {
+    fn from(errors: ValidationErrors) -> Self {
+        let mut fields: Vec<FieldError> = errors
+            .field_errors()
+            .into_iter()
+            .flat_map(|(field, errors)| {
+                errors.iter().map(move |error| FieldError {
+                    field: field.to_string(),
+                    message: error
+                        .message
+                        .as_ref()
+                        .map(|message| message.to_string())
+                        .unwrap_or_else(|| "Invalid value".into()),
+                })
+            })
+            .collect();
+        // Field order from the validator is unspecified.
+        fields.sort_by(|a, b| a.field.cmp(&b.field));
+
+        ApiError::Validation(fields)
+    }
+}
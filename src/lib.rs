@@ -1,5 +1,9 @@
 pub mod db;
+pub mod errors;
 pub mod mail;
 pub mod middlewares;
+pub mod repositories;
 pub mod services;
+pub mod storage;
+pub mod tasks;
 pub mod utils;
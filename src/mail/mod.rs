@@ -1,2 +1,2 @@
 pub mod send;
-pub mod templates;
\ No newline at end of file
+pub mod templates;
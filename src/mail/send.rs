@@ -86,13 +86,13 @@ pub fn send_mail(options: MailOptions) {
 
     match mailer.send(&message) {
         Ok(_) => {
-            println!("Email sent successfully!");
+            eprintln!("Email sent successfully!");
             if let Err(e) = save_to_sent_folder(&message) {
-                println!("Failed to save to Sent folder: {:?}", e);
+                eprintln!("Failed to save to Sent folder: {:?}", e);
             }
         }
         Err(e) => {
-            println!("Failed to send email: {e:?}");
+            eprintln!("Failed to send email: {e:?}");
         }
     }
 }
@@ -1 +1,2 @@
-pub mod verification;
\ No newline at end of file
+pub mod password_reset;
+pub mod verification;
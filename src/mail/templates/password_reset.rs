@@ -0,0 +1,69 @@
+use std::env;
+
+pub fn password_reset_template(
+    name: String,
+    email: String,
+    token: String,
+) -> (String, String, String, String, String) {
+    let subject = "Reset Your Password".to_string();
+    let to = format!("{} <{}>", &name, &email);
+    let smtp_reset_name = "Account Security".to_string();
+    let smtp_reset_email =
+        env::var("SMTP_VERIFICATION_EMAIL").expect("SMTP_VERIFICATION_EMAIL must be set");
+    let html = format!(
+        r#"
+        <!DOCTYPE html>
+        <html>
+        <head>
+            <meta charset="UTF-8">
+            <title>Reset Your Password</title>
+            <style>
+                body {{
+                    font-family: Arial, sans-serif;
+                    background-color: #f5f5f5;
+                }}
+
+                .container {{
+                    max-width: 600px;
+                    margin: 0 auto;
+                    padding: 20px;
+                    background-color: #fff;
+                    border-radius: 5px;
+                    box-shadow: 0 2px 5px rgba(0, 0, 0, 0.1);
+                }}
+
+                h1 {{
+                    color: #333;
+                    text-align: center;
+                    margin-bottom: 20px;
+                }}
+
+                p {{
+                    color: #666;
+                    line-height: 1.5;
+                    margin-bottom: 10px;
+                }}
+
+                a {{
+                    color: #007bff;
+                    text-decoration: none;
+                    font-weight: bold;
+                }}
+            </style>
+        </head>
+        <body>
+            <div class="container">
+                <h1>Hello, {}!</h1>
+                <p>We received a request to reset your password. Click the link below to choose a new one:</p>
+                <p><a href="http://localhost:3000/reset-password?token={}">Reset Your Password</a></p>
+                <p>This link expires in 24 hours and can only be used once.</p>
+                <p>If you didn't request a password reset, please ignore this email.</p>
+            </div>
+        </body>
+        </html>
+        "#,
+        &name, &token
+    );
+
+    (subject, to, html, smtp_reset_name, smtp_reset_email)
+}
@@ -1,13 +1,55 @@
 use actix_cors::Cors;
-use actix_web::{get, http::header, web::Data, App, HttpResponse, HttpServer, Responder};
-use db::connection::{establish_pool, AppState};
-use middlewares::auth::Authentication;
+use actix_web::{
+    get,
+    http::header,
+    web::{Data, JsonConfig, PathConfig, QueryConfig},
+    App, HttpResponse, HttpServer, Responder,
+};
+use chrono::TimeDelta;
+use db::connection::{establish_pool, AppState, PoolConfig};
+use errors::ApiError;
+use middlewares::{
+    auth::Authentication,
+    request_id::{RequestId, REQUEST_ID_HEADER},
+};
+use repositories::{
+    postgres::{PgPostRepository, PgUserRepository},
+    PostRepository, UserRepository,
+};
 use server::*;
 use services::{
-    posts::{create_post, delete_post, get_post, get_posts, update_post},
-    users::{check_auth, login, logout, register},
+    comments::{
+        create_comment, delete_comment, get_comments, get_moderation_queue, moderate_comment,
+        update_comment,
+    },
+    feeds::{get_author_feed, get_feed, get_tag_feed},
+    media::{delete_media, get_media, get_media_file, get_media_list, upload_media},
+    posts::{
+        archive_post, create_post, delete_post, get_post, get_post_by_slug, get_posts, get_trash,
+        patch_post, publish_post, purge_post, restore_post, set_post_cover, unpublish_post,
+        update_post,
+    },
+    revisions::{diff_revisions, get_revision, get_revisions, restore_revision},
+    search::search_posts,
+    sitemap::{get_robots, get_sitemap, get_sitemap_page},
+    taxonomy::{
+        get_categories, get_category_posts, get_tag_posts, get_tags, merge_tag, rename_tag,
+    },
+    users::{
+        bootstrap_admin, confirm_email, forgot_password, get_sessions, get_users, login, logout,
+        refresh_access_token, register, resend_verification, reset_password, revoke_other_sessions,
+        revoke_session, update_user_role, verify_email,
+    },
+};
+use std::{env, sync::Arc};
+use storage::storage_from_env;
+use tasks::{
+    media_variants::DEFAULT_IMAGE_VARIANTS,
+    render_backfill::spawn_render_backfill,
+    scheduled_posts::spawn_scheduled_publisher,
+    trash_purge::{spawn_trash_purger, DEFAULT_RETENTION_DAYS},
 };
-use std::env;
+use utils::images::{parse_variant_specs, VariantSpec};
 
 #[get("/")]
 async fn hello() -> impl Responder {
@@ -20,7 +62,40 @@ async fn hello() -> impl Responder {
 async fn main() -> std::io::Result<()> {
     dotenv::dotenv().ok();
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let pool = establish_pool(database_url);
+    let pool = establish_pool(database_url, PoolConfig::from_env());
+
+    let trash_retention_days = match env::var("TRASH_RETENTION_DAYS") {
+        Ok(days) => days
+            .parse()
+            .expect("TRASH_RETENTION_DAYS must be a whole number of days"),
+        Err(_) => DEFAULT_RETENTION_DAYS,
+    };
+
+    let post_repository: Arc<dyn PostRepository> = Arc::new(PgPostRepository::new(pool.clone()));
+    let user_repository: Arc<dyn UserRepository> = Arc::new(PgUserRepository::new(pool.clone()));
+    // Registrations start as readers; ADMIN_EMAIL names the account that gets
+    // admin rights on startup, so a fresh install has someone to manage roles.
+    if let Ok(admin_email) = env::var("ADMIN_EMAIL") {
+        match bootstrap_admin(user_repository.as_ref(), &admin_email) {
+            Ok(true) => eprintln!("{} is an admin", admin_email),
+            Ok(false) => eprintln!(
+                "ADMIN_EMAIL {} has not registered yet; restart after registering",
+                admin_email
+            ),
+            Err(e) => eprintln!("Failed to promote {} to admin: {}", admin_email, e),
+        }
+    }
+
+    let storage = storage_from_env();
+    let image_variants: Arc<[VariantSpec]> = parse_variant_specs(
+        &env::var("IMAGE_VARIANTS").unwrap_or_else(|_| DEFAULT_IMAGE_VARIANTS.into()),
+    )
+    .expect("IMAGE_VARIANTS is invalid")
+    .into();
+
+    spawn_scheduled_publisher(pool.clone());
+    spawn_render_backfill(pool.clone());
+    spawn_trash_purger(pool.clone(), TimeDelta::days(trash_retention_days));
 
     HttpServer::new(move || {
         let cors = Cors::default()
@@ -28,26 +103,98 @@ async fn main() -> std::io::Result<()> {
             .allowed_origin_fn(|origin, _req_head| {
                 origin.as_bytes().ends_with(b".rishabhportfolio.site")
             })
-            .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
-            .allowed_headers(vec![header::AUTHORIZATION, header::ACCEPT])
+            .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE"])
+            .allowed_headers(vec![
+                header::AUTHORIZATION,
+                header::ACCEPT,
+                header::IF_MATCH,
+                header::IF_NONE_MATCH,
+                REQUEST_ID_HEADER,
+            ])
             .allowed_header(header::CONTENT_TYPE)
+            .expose_headers(vec![header::ETAG, REQUEST_ID_HEADER])
             .max_age(3600)
             .supports_credentials();
 
         App::new()
+            .wrap(RequestId)
             .wrap(cors)
             .wrap(Authentication)
-            .app_data(Data::new(AppState { pool: pool.clone() }))
+            .app_data(Data::new(AppState {
+                pool: Some(pool.clone()),
+                posts: post_repository.clone(),
+                users: user_repository.clone(),
+                storage: storage.clone(),
+                image_variants: image_variants.clone(),
+            }))
+            // Malformed bodies, queries and paths get the same error shape as
+            // everything else.
+            .app_data(JsonConfig::default().error_handler(|err, _| {
+                ApiError::BadRequest(format!("Invalid request body: {}", err)).into()
+            }))
+            .app_data(QueryConfig::default().error_handler(|err, _| {
+                ApiError::BadRequest(format!("Invalid query string: {}", err)).into()
+            }))
+            .app_data(PathConfig::default().error_handler(|err, _| {
+                ApiError::BadRequest(format!("Invalid path: {}", err)).into()
+            }))
             .service(hello)
             .service(login)
-            .service(check_auth)
+            .service(refresh_access_token)
             .service(logout)
+            .service(get_sessions)
+            .service(revoke_other_sessions)
+            .service(revoke_session)
+            .service(get_users)
+            .service(update_user_role)
             .service(register)
+            .service(verify_email)
+            .service(confirm_email)
+            .service(resend_verification)
+            .service(forgot_password)
+            .service(reset_password)
             .service(get_posts)
+            .service(search_posts)
+            .service(get_post_by_slug)
+            .service(get_trash)
             .service(get_post)
             .service(create_post)
             .service(update_post)
+            .service(patch_post)
+            .service(set_post_cover)
             .service(delete_post)
+            .service(restore_post)
+            .service(purge_post)
+            .service(publish_post)
+            .service(unpublish_post)
+            .service(archive_post)
+            .service(get_revisions)
+            .service(diff_revisions)
+            .service(get_revision)
+            .service(restore_revision)
+            .service(get_comments)
+            .service(create_comment)
+            .service(get_moderation_queue)
+            .service(update_comment)
+            .service(delete_comment)
+            .service(moderate_comment)
+            .service(get_tags)
+            .service(get_tag_posts)
+            .service(rename_tag)
+            .service(merge_tag)
+            .service(get_categories)
+            .service(get_category_posts)
+            .service(get_feed)
+            .service(get_author_feed)
+            .service(get_tag_feed)
+            .service(get_sitemap)
+            .service(get_sitemap_page)
+            .service(get_robots)
+            .service(upload_media)
+            .service(get_media_list)
+            .service(get_media_file)
+            .service(get_media)
+            .service(delete_media)
     })
     .bind(("127.0.0.1", 5000))?
     .run()
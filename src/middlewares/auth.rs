@@ -1,19 +1,77 @@
-use crate::utils::hashing::decode_jwt;
+use crate::{
+    db::{connection::AppState, models::Role},
+    errors::ApiError,
+    utils::hashing::{decode_jwt, Claims, JwtMETHODS},
+};
 use actix_web::{
-    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    error::ErrorUnauthorized,
-    Error, HttpMessage,
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header,
+    web::Data,
+    Error, FromRequest, HttpMessage, HttpRequest,
 };
 use std::{
     future::{ready, Future, Ready},
     pin::Pin,
+    rc::Rc,
 };
 
+/// Parses the bearer token on every request. Valid tokens are stored in the
+/// request extensions for the `AuthenticatedUser` and `OptionalUser`
+/// extractors; handlers declare whether they need them.
 pub struct Authentication;
 
+/// The user and session a request was authenticated with. Taking this as a
+/// handler argument makes the route require a valid access token.
+#[derive(Clone, Debug)]
+pub struct AuthenticatedUser {
+    pub user_id: String,
+    pub session_id: String,
+    pub role: Role,
+}
+
+/// Like `AuthenticatedUser`, but anonymous requests (or ones with a bad
+/// token) get `None` instead of a 401.
+#[derive(Clone, Debug)]
+pub struct OptionalUser(pub Option<AuthenticatedUser>);
+
+/// Why the bearer token on the request was rejected.
+#[derive(Clone, Debug)]
+struct AuthenticationError(String);
+
+impl FromRequest for AuthenticatedUser {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let extensions = req.extensions();
+
+        ready(match extensions.get::<AuthenticatedUser>() {
+            Some(user) => Ok(user.clone()),
+            None => {
+                let message = extensions
+                    .get::<AuthenticationError>()
+                    .map(|e| e.0.clone())
+                    .unwrap_or_else(|| "Missing or invalid token".into());
+                Err(ApiError::Unauthorized(message).into())
+            }
+        })
+    }
+}
+
+impl FromRequest for OptionalUser {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(OptionalUser(
+            req.extensions().get::<AuthenticatedUser>().cloned(),
+        )))
+    }
+}
+
 impl<S, B> Transform<S, ServiceRequest> for Authentication
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
@@ -24,17 +82,19 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(AuthenticationMiddleware { service }))
+        ready(Ok(AuthenticationMiddleware {
+            service: Rc::new(service),
+        }))
     }
 }
 
 pub struct AuthenticationMiddleware<S> {
-    service: S,
+    service: Rc<S>,
 }
 
 impl<S, B> Service<ServiceRequest> for AuthenticationMiddleware<S>
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
@@ -45,63 +105,68 @@ where
     forward_ready!(service);
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        let user_id_cookie = req.cookie("auth_token");
-
-        let path = req.path();
-        let protected_routes = match path {
-            p if p.starts_with("/posts") => vec!["/update", "/create", "/delete"],
-            p if p.starts_with("/users") => vec!["/logout"],
-            _ => return Box::pin(self.service.call(req)),
-        };
-
-        for route in protected_routes {
-            if path.ends_with(route) {
-                if let Some(cookie) = user_id_cookie {
-                    let cookie_token = cookie.value();
-                    return match validate_jwt(&req, cookie_token) {
-                        Ok(sub) => {
-                            req.extensions_mut().insert(sub);
-                            Box::pin(self.service.call(req))
-                        }
-                        Err(e) => Box::pin(async move { Err(ErrorUnauthorized(e)) }),
-                    };
-                } else {
-                    return Box::pin(async move {
-                        Err(ErrorUnauthorized("auth_token cookie not found!"))
-                    });
+        let service = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            if req.headers().contains_key(header::AUTHORIZATION) {
+                let validated = match validate_jwt(&req) {
+                    Ok(claims) => validate_session(&req, claims).await,
+                    Err(e) => Err(e),
+                };
+
+                match validated {
+                    Ok((claims, role)) => {
+                        req.extensions_mut().insert(AuthenticatedUser {
+                            user_id: claims.sub,
+                            session_id: claims.jti,
+                            role,
+                        });
+                    }
+                    Err(e) => {
+                        req.extensions_mut().insert(AuthenticationError(e));
+                    }
                 }
             }
-        }
 
-        Box::pin(self.service.call(req))
+            service.call(req).await
+        })
     }
 }
 
-fn validate_jwt(req: &ServiceRequest, cookie_token: &str) -> Result<String, String> {
-    match decode_jwt(cookie_token) {
-        Ok(cookie_data) => {
-            let authorization = req.headers().get("Authorization");
-
-            if let Some(auth_data) = authorization {
-                let auth_val = auth_data
-                    .to_str()
-                    .map_err(|_| "Error converting Authorization header to string")?;
-                let header_token = &auth_val[7..];
-
-                match decode_jwt(header_token) {
-                    Ok(header_data) => {
-                        if cookie_data.sub == header_data.sub {
-                            Ok(header_data.sub)
-                        } else {
-                            Err("User ID mismatch between cookie and header!".into())
-                        }
-                    }
-                    Err(err) => Err(format!("Invalid JWT in Authorization header: {}", err)),
-                }
-            } else {
-                Err("Authorization header not found!".into())
-            }
-        }
-        Err(err) => Err(format!("Invalid JWT in auth_token cookie: {}", err)),
+fn validate_jwt(req: &ServiceRequest) -> Result<Claims, String> {
+    let auth_data = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .ok_or("Authorization header not found!")?;
+    let auth_val = auth_data
+        .to_str()
+        .map_err(|_| "Error converting Authorization header to string")?;
+    let header_token = auth_val
+        .strip_prefix("Bearer ")
+        .ok_or("Authorization header must use the Bearer scheme!")?;
+
+    decode_jwt(header_token, JwtMETHODS::Access)
+        .map_err(|err| format!("Invalid JWT in Authorization header: {}", err))
+}
+
+/// Checks the token's session is still active and loads the user's current
+/// role, so role changes apply without waiting for the token to expire.
+async fn validate_session(req: &ServiceRequest, claims: Claims) -> Result<(Claims, Role), String> {
+    let data = req
+        .app_data::<Data<AppState>>()
+        .ok_or("Application state not configured")?;
+
+    let role = data
+        .blocking({
+            let session_id = claims.jti.clone();
+            let user_id = claims.sub.clone();
+            move |data| Ok(data.users.touch_session(&session_id, &user_id)?)
+        })
+        .await;
+
+    match role {
+        Ok(Some(role)) => Ok((claims, role)),
+        Ok(None) => Err("Session has been revoked or does not exist!".into()),
+        Err(_) => Err("An error occurred while checking the session".into()),
     }
 }
@@ -1 +1,3 @@
 pub mod auth;
+pub mod permissions;
+pub mod request_id;
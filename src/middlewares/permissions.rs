@@ -0,0 +1,86 @@
+use crate::{db::models::Role, errors::ApiError, middlewares::auth::AuthenticatedUser};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    CreatePost,
+    EditOwnPost,
+    EditAnyPost,
+    PublishOwnPost,
+    PublishAnyPost,
+    DeleteOwnPost,
+    DeleteAnyPost,
+    ManageUsers,
+    ManageTaxonomy,
+    ModerateOwnPostComments,
+    ModerateAnyComment,
+    UploadMedia,
+    ManageAnyMedia,
+}
+
+impl Role {
+    /// The permission matrix. Admins can do everything (including managing
+    /// users, renaming or merging tags, moderating any comment and deleting
+    /// anyone's media), editors can edit and publish anyone's posts, authors
+    /// only manage their own posts, media and the comments on them, and
+    /// readers only read and comment.
+    pub fn can(&self, permission: Permission) -> bool {
+        use Permission::*;
+
+        match self {
+            Role::Admin => true,
+            Role::Editor => matches!(
+                permission,
+                CreatePost
+                    | EditOwnPost
+                    | EditAnyPost
+                    | PublishOwnPost
+                    | PublishAnyPost
+                    | DeleteOwnPost
+                    | ModerateOwnPostComments
+                    | UploadMedia
+            ),
+            Role::Author => matches!(
+                permission,
+                CreatePost
+                    | EditOwnPost
+                    | PublishOwnPost
+                    | DeleteOwnPost
+                    | ModerateOwnPostComments
+                    | UploadMedia
+            ),
+            Role::Reader => false,
+        }
+    }
+}
+
+impl AuthenticatedUser {
+    /// Guard for actions that don't depend on who owns the resource.
+    pub fn authorize(&self, permission: Permission, action: &str) -> Result<(), ApiError> {
+        if self.role.can(permission) {
+            Ok(())
+        } else {
+            Err(forbidden(action))
+        }
+    }
+
+    /// Guard for actions on a resource with an owner: `own` applies when the
+    /// user owns it, `any` otherwise.
+    pub fn authorize_owned(
+        &self,
+        owner_id: Option<&str>,
+        own: Permission,
+        any: Permission,
+        action: &str,
+    ) -> Result<(), ApiError> {
+        let permission = if owner_id == Some(self.user_id.as_str()) {
+            own
+        } else {
+            any
+        };
+        self.authorize(permission, action)
+    }
+}
+
+fn forbidden(action: &str) -> ApiError {
+    ApiError::Forbidden(format!("You do not have permission to {}", action))
+}
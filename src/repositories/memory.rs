@@ -0,0 +1,732 @@
+use chrono::{NaiveDateTime, SubsecRound, Utc};
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+};
+
+use super::{
+    KeysetPage, PostEdit, PostFilter, PostListing, PostRepository, RefreshOutcome, RepositoryError,
+    RepositoryResult, UserRepository, EXCERPT_LENGTH,
+};
+use crate::db::models::{
+    CreatePost, CreateRefreshToken, CreateSession, CreateUser, Post, PostStatus, RefreshToken,
+    Role, Session, Term, User,
+};
+
+/// Locks the store; a panic while it was held shouldn't make it unusable.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// The current time at the microsecond precision Postgres keeps, so cursors
+/// round-trip like they do against the database.
+fn now() -> NaiveDateTime {
+    Utc::now().naive_utc().trunc_subsecs(6)
+}
+
+#[derive(Default)]
+struct PostStore {
+    posts: Vec<Post>,
+    /// Former slug to post id.
+    slug_history: HashMap<String, String>,
+    tags: HashMap<String, Vec<Term>>,
+    categories: HashMap<String, Vec<Term>>,
+}
+
+/// Keeps posts in memory, mirroring the constraints the database enforces.
+/// Revisions aren't kept.
+#[derive(Default)]
+pub struct InMemoryPostRepository {
+    store: Mutex<PostStore>,
+}
+
+impl InMemoryPostRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Seeds an existing post, such as one in the trash or with a history.
+    pub fn insert(&self, post: Post) {
+        lock(&self.store).posts.push(post);
+    }
+
+    /// Records `slug` as a former slug of the post.
+    pub fn insert_former_slug(&self, slug: &str, post_id: &str) {
+        lock(&self.store)
+            .slug_history
+            .insert(slug.to_string(), post_id.to_string());
+    }
+}
+
+fn sorted_terms(terms: &[Term]) -> Vec<Term> {
+    let mut terms = terms.to_vec();
+    terms.sort_by(|a, b| a.name.cmp(&b.name));
+    terms
+}
+
+fn terms_of(terms: &HashMap<String, Vec<Term>>, post_ids: &[&str]) -> HashMap<String, Vec<Term>> {
+    post_ids
+        .iter()
+        .filter_map(|id| Some((id.to_string(), terms.get(*id)?.clone())))
+        .collect()
+}
+
+fn has_term(terms: &HashMap<String, Vec<Term>>, post_id: &str, slug: &str) -> bool {
+    terms
+        .get(post_id)
+        .is_some_and(|terms| terms.iter().any(|term| term.slug == slug))
+}
+
+impl PostStore {
+    fn matching<'a>(&'a self, filter: &'a PostFilter) -> impl Iterator<Item = &'a Post> + 'a {
+        self.posts.iter().filter(move |post| {
+            post.status == filter.status
+                && post.deleted_at.is_none()
+                && filter
+                    .author
+                    .as_ref()
+                    .is_none_or(|author| post.user_id.as_ref() == Some(author))
+                && filter.from.is_none_or(|from| post.created_at >= from)
+                && filter.to.is_none_or(|to| post.created_at <= to)
+                && filter
+                    .tag
+                    .as_ref()
+                    .is_none_or(|tag| has_term(&self.tags, &post.id, tag))
+                && filter
+                    .category
+                    .as_ref()
+                    .is_none_or(|category| has_term(&self.categories, &post.id, category))
+        })
+    }
+
+    fn live_post_mut(&mut self, id: &str) -> Option<&mut Post> {
+        self.posts
+            .iter_mut()
+            .find(|post| post.id == id && post.deleted_at.is_none())
+    }
+
+    fn trashed_post_mut(&mut self, id: &str) -> Option<&mut Post> {
+        self.posts
+            .iter_mut()
+            .find(|post| post.id == id && post.deleted_at.is_some())
+    }
+}
+
+fn listing(post: &Post) -> PostListing {
+    PostListing {
+        id: post.id.clone(),
+        slug: post.slug.clone(),
+        title: post.title.clone(),
+        excerpt: post
+            .excerpt
+            .clone()
+            .unwrap_or_else(|| post.body.chars().take(EXCERPT_LENGTH as usize).collect()),
+        user_id: post.user_id.clone(),
+        status: post.status,
+        created_at: post.created_at,
+        updated_at: post.updated_at,
+        published_at: post.published_at,
+        cover_image_url: post.cover_image_url.clone(),
+    }
+}
+
+impl PostRepository for InMemoryPostRepository {
+    fn find(&self, id: &str) -> RepositoryResult<Option<Post>> {
+        Ok(lock(&self.store)
+            .posts
+            .iter()
+            .find(|post| post.id == id && post.deleted_at.is_none())
+            .cloned())
+    }
+
+    fn find_by_slug(&self, slug: &str) -> RepositoryResult<Option<Post>> {
+        Ok(lock(&self.store)
+            .posts
+            .iter()
+            .find(|post| post.slug == slug && post.deleted_at.is_none())
+            .cloned())
+    }
+
+    fn find_by_former_slug(&self, slug: &str) -> RepositoryResult<Option<Post>> {
+        let store = lock(&self.store);
+        let Some(post_id) = store.slug_history.get(slug) else {
+            return Ok(None);
+        };
+
+        Ok(store
+            .posts
+            .iter()
+            .find(|post| &post.id == post_id && post.deleted_at.is_none())
+            .cloned())
+    }
+
+    fn title_exists(&self, title: &str) -> RepositoryResult<bool> {
+        Ok(lock(&self.store)
+            .posts
+            .iter()
+            .any(|post| post.title == title))
+    }
+
+    fn slug_taken(&self, slug: &str, except_post_id: Option<&str>) -> RepositoryResult<bool> {
+        let store = lock(&self.store);
+        let other = |post_id: &str| Some(post_id) != except_post_id;
+
+        Ok(store
+            .posts
+            .iter()
+            .any(|post| post.slug == slug && other(&post.id))
+            || store
+                .slug_history
+                .get(slug)
+                .is_some_and(|post_id| other(post_id)))
+    }
+
+    fn create(
+        &self,
+        new_post: CreatePost,
+        tags: Option<&[Term]>,
+        categories: Option<&[Term]>,
+    ) -> RepositoryResult<Post> {
+        let mut store = lock(&self.store);
+        if store
+            .posts
+            .iter()
+            .any(|post| post.title == new_post.title || post.slug == new_post.slug)
+        {
+            return Err(RepositoryError::Conflict(
+                "Title or slug already exists".into(),
+            ));
+        }
+
+        let post = Post {
+            id: new_post.id,
+            title: new_post.title,
+            body: new_post.body,
+            user_id: Some(new_post.user_id),
+            created_at: now(),
+            updated_at: None,
+            status: PostStatus::Draft,
+            published_at: None,
+            slug: new_post.slug,
+            body_format: new_post.body_format,
+            rendered_body: new_post.rendered_body,
+            version: 1,
+            excerpt: None,
+            cover_image_url: None,
+            deleted_at: None,
+            cover_media_id: None,
+        };
+
+        if let Some(tags) = tags {
+            store.tags.insert(post.id.clone(), sorted_terms(tags));
+        }
+        if let Some(categories) = categories {
+            store
+                .categories
+                .insert(post.id.clone(), sorted_terms(categories));
+        }
+        store.posts.push(post.clone());
+
+        Ok(post)
+    }
+
+    fn tags(&self, post_ids: &[&str]) -> RepositoryResult<HashMap<String, Vec<Term>>> {
+        Ok(terms_of(&lock(&self.store).tags, post_ids))
+    }
+
+    fn categories(&self, post_ids: &[&str]) -> RepositoryResult<HashMap<String, Vec<Term>>> {
+        Ok(terms_of(&lock(&self.store).categories, post_ids))
+    }
+
+    fn count(&self, filter: &PostFilter) -> RepositoryResult<i64> {
+        Ok(lock(&self.store).matching(filter).count() as i64)
+    }
+
+    fn list(&self, filter: &PostFilter, page: &KeysetPage) -> RepositoryResult<Vec<PostListing>> {
+        let store = lock(&self.store);
+        let mut posts: Vec<&Post> = store
+            .matching(filter)
+            .filter(|post| {
+                page.after.as_ref().is_none_or(|(created_at, id)| {
+                    let key = (post.created_at, &post.id);
+                    if page.ascending {
+                        key > (*created_at, id)
+                    } else {
+                        key < (*created_at, id)
+                    }
+                })
+            })
+            .collect();
+
+        posts.sort_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+        if !page.ascending {
+            posts.reverse();
+        }
+
+        Ok(posts
+            .into_iter()
+            .take(page.limit as usize)
+            .map(listing)
+            .collect())
+    }
+
+    fn update(&self, current: &Post, edit: PostEdit) -> RepositoryResult<Option<Post>> {
+        let mut store = lock(&self.store);
+        let changes = edit.changes;
+
+        let taken = store.posts.iter().any(|post| {
+            post.id != current.id
+                && (changes.title.as_ref() == Some(&post.title)
+                    || changes.slug.as_ref() == Some(&post.slug))
+        });
+        if taken {
+            return Err(RepositoryError::Conflict(
+                "Title or slug already exists".into(),
+            ));
+        }
+
+        let Some(post) = store
+            .posts
+            .iter_mut()
+            .find(|post| post.id == current.id && post.version == current.version)
+        else {
+            return Ok(None);
+        };
+
+        if let Some(title) = changes.title {
+            post.title = title;
+        }
+        if let Some(body) = changes.body {
+            post.body = body;
+        }
+        if let Some(body_format) = changes.body_format {
+            post.body_format = body_format;
+        }
+        if let Some(rendered_body) = changes.rendered_body {
+            post.rendered_body = rendered_body;
+        }
+        if let Some(status) = changes.status {
+            post.status = status;
+        }
+        if let Some(published_at) = changes.published_at {
+            post.published_at = published_at;
+        }
+        if let Some(excerpt) = changes.excerpt {
+            post.excerpt = excerpt;
+        }
+        if let Some(cover_image_url) = changes.cover_image_url {
+            post.cover_image_url = cover_image_url;
+        }
+        if let Some(cover_media_id) = changes.cover_media_id {
+            post.cover_media_id = cover_media_id;
+        }
+        let former_slug = changes
+            .slug
+            .map(|slug| std::mem::replace(&mut post.slug, slug))
+            .filter(|former_slug| *former_slug != post.slug);
+        post.updated_at = Some(now());
+        post.version += 1;
+        let updated_post = post.clone();
+
+        if let Some(former_slug) = former_slug {
+            store.slug_history.remove(&updated_post.slug);
+            store
+                .slug_history
+                .insert(former_slug, updated_post.id.clone());
+        }
+        if let Some(tags) = edit.tags {
+            store
+                .tags
+                .insert(updated_post.id.clone(), sorted_terms(&tags));
+        }
+        if let Some(categories) = edit.categories {
+            store
+                .categories
+                .insert(updated_post.id.clone(), sorted_terms(&categories));
+        }
+
+        Ok(Some(updated_post))
+    }
+
+    fn set_status(
+        &self,
+        id: &str,
+        status: PostStatus,
+        published_at: Option<NaiveDateTime>,
+    ) -> RepositoryResult<Option<Post>> {
+        Ok(lock(&self.store).live_post_mut(id).map(|post| {
+            post.status = status;
+            post.published_at = published_at;
+            post.version += 1;
+            post.clone()
+        }))
+    }
+
+    fn trash(&self, id: &str, expected_version: i32) -> RepositoryResult<bool> {
+        let mut store = lock(&self.store);
+        let Some(post) = store
+            .live_post_mut(id)
+            .filter(|post| post.version == expected_version)
+        else {
+            return Ok(false);
+        };
+
+        post.deleted_at = Some(now());
+        post.version += 1;
+        Ok(true)
+    }
+
+    fn list_trash(&self, author_id: Option<&str>) -> RepositoryResult<Vec<Post>> {
+        let mut trashed: Vec<Post> = lock(&self.store)
+            .posts
+            .iter()
+            .filter(|post| {
+                post.deleted_at.is_some()
+                    && author_id.is_none_or(|author_id| post.user_id.as_deref() == Some(author_id))
+            })
+            .cloned()
+            .collect();
+
+        trashed.sort_by_key(|post| Reverse(post.deleted_at));
+        Ok(trashed)
+    }
+
+    fn find_trashed(&self, id: &str) -> RepositoryResult<Option<Post>> {
+        Ok(lock(&self.store)
+            .trashed_post_mut(id)
+            .map(|post| post.clone()))
+    }
+
+    fn restore(&self, id: &str) -> RepositoryResult<Option<Post>> {
+        Ok(lock(&self.store).trashed_post_mut(id).map(|post| {
+            post.deleted_at = None;
+            post.version += 1;
+            post.clone()
+        }))
+    }
+
+    fn purge(&self, id: &str) -> RepositoryResult<bool> {
+        let mut store = lock(&self.store);
+        if store.trashed_post_mut(id).is_none() {
+            return Ok(false);
+        }
+
+        store.posts.retain(|post| post.id != id);
+        store.slug_history.retain(|_, post_id| post_id != id);
+        store.tags.remove(id);
+        store.categories.remove(id);
+        Ok(true)
+    }
+}
+
+#[derive(Default)]
+struct UserStore {
+    users: Vec<User>,
+    sessions: Vec<Session>,
+    refresh_tokens: Vec<RefreshToken>,
+}
+
+/// Keeps users and their sessions in memory, with emails unique like in the
+/// database.
+#[derive(Default)]
+pub struct InMemoryUserRepository {
+    store: Mutex<UserStore>,
+}
+
+impl InMemoryUserRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Seeds an existing user, such as a verified one or an admin.
+    pub fn insert(&self, user: User) {
+        lock(&self.store).users.push(user);
+    }
+}
+
+impl UserRepository for InMemoryUserRepository {
+    fn find(&self, id: &str) -> RepositoryResult<Option<User>> {
+        Ok(lock(&self.store)
+            .users
+            .iter()
+            .find(|user| user.id == id)
+            .cloned())
+    }
+
+    fn find_by_email(&self, email: &str) -> RepositoryResult<Option<User>> {
+        Ok(lock(&self.store)
+            .users
+            .iter()
+            .find(|user| user.email == email)
+            .cloned())
+    }
+
+    fn names(&self, ids: &[&str]) -> RepositoryResult<HashMap<String, String>> {
+        Ok(lock(&self.store)
+            .users
+            .iter()
+            .filter(|user| ids.contains(&user.id.as_str()))
+            .map(|user| (user.id.clone(), user.name.clone()))
+            .collect())
+    }
+
+    fn create(&self, new_user: CreateUser) -> RepositoryResult<User> {
+        let mut store = lock(&self.store);
+        if store.users.iter().any(|user| user.email == new_user.email) {
+            return Err(RepositoryError::Conflict("User already exists".into()));
+        }
+
+        let user = User {
+            id: new_user.id,
+            name: new_user.name,
+            email: new_user.email,
+            password: Some(new_user.password),
+            verified: false,
+            created_at: now(),
+            updated_at: None,
+            verification_sent_at: new_user.verification_sent_at,
+            password_changed_at: None,
+            role: Role::Reader,
+        };
+        store.users.push(user.clone());
+
+        Ok(user)
+    }
+
+    fn list(&self) -> RepositoryResult<Vec<User>> {
+        let mut users = lock(&self.store).users.clone();
+        users.sort_by_key(|user| user.created_at);
+        Ok(users)
+    }
+
+    fn update_role(&self, id: &str, role: Role) -> RepositoryResult<Option<User>> {
+        let mut store = lock(&self.store);
+        let mut admins = store.users.iter().filter(|user| user.role == Role::Admin);
+        if role != Role::Admin
+            && admins.next().is_some_and(|admin| admin.id == id)
+            && admins.next().is_none()
+        {
+            return Err(RepositoryError::Conflict(
+                "The last admin cannot be demoted".into(),
+            ));
+        }
+
+        Ok(store
+            .users
+            .iter_mut()
+            .find(|user| user.id == id)
+            .map(|user| {
+                user.role = role;
+                user.clone()
+            }))
+    }
+
+    fn mark_verified(&self, id: &str) -> RepositoryResult<()> {
+        if let Some(user) = lock(&self.store)
+            .users
+            .iter_mut()
+            .find(|user| user.id == id)
+        {
+            user.verified = true;
+        }
+        Ok(())
+    }
+
+    fn claim_verification_resend(
+        &self,
+        id: &str,
+        cooldown_start: NaiveDateTime,
+        now: NaiveDateTime,
+    ) -> RepositoryResult<bool> {
+        let mut store = lock(&self.store);
+        let Some(user) = store.users.iter_mut().find(|user| {
+            user.id == id
+                && user
+                    .verification_sent_at
+                    .is_none_or(|sent_at| sent_at < cooldown_start)
+        }) else {
+            return Ok(false);
+        };
+
+        user.verification_sent_at = Some(now);
+        Ok(true)
+    }
+
+    fn reset_password(
+        &self,
+        id: &str,
+        hashed_password: String,
+        issued_at: NaiveDateTime,
+    ) -> RepositoryResult<bool> {
+        let mut store = lock(&self.store);
+        let now = now();
+        let Some(user) = store.users.iter_mut().find(|user| {
+            user.id == id
+                && user
+                    .password_changed_at
+                    .is_none_or(|changed_at| changed_at < issued_at)
+        }) else {
+            return Ok(false);
+        };
+
+        user.password = Some(hashed_password);
+        user.password_changed_at = Some(now);
+        for session in store
+            .sessions
+            .iter_mut()
+            .filter(|session| session.user_id == id && session.revoked_at.is_none())
+        {
+            session.revoked_at = Some(now);
+        }
+        Ok(true)
+    }
+
+    fn create_session(
+        &self,
+        session: CreateSession,
+        refresh_token: CreateRefreshToken,
+    ) -> RepositoryResult<()> {
+        let mut store = lock(&self.store);
+        let now = now();
+
+        store.sessions.push(Session {
+            id: session.id,
+            user_id: session.user_id,
+            created_at: now,
+            last_seen_at: now,
+            user_agent: session.user_agent,
+            ip_address: session.ip_address,
+            revoked_at: None,
+        });
+        store.refresh_tokens.push(RefreshToken {
+            id: refresh_token.id,
+            session_id: refresh_token.session_id,
+            token_hash: refresh_token.token_hash,
+            created_at: now,
+            expires_at: refresh_token.expires_at,
+            used_at: None,
+        });
+        Ok(())
+    }
+
+    fn touch_session(&self, session_id: &str, user_id: &str) -> RepositoryResult<Option<Role>> {
+        let mut store = lock(&self.store);
+        let Some(session) = store.sessions.iter_mut().find(|session| {
+            session.id == session_id && session.user_id == user_id && session.revoked_at.is_none()
+        }) else {
+            return Ok(None);
+        };
+
+        session.last_seen_at = now();
+        Ok(store
+            .users
+            .iter()
+            .find(|user| user.id == user_id)
+            .map(|user| user.role))
+    }
+
+    fn active_sessions(&self, user_id: &str) -> RepositoryResult<Vec<Session>> {
+        let mut sessions: Vec<Session> = lock(&self.store)
+            .sessions
+            .iter()
+            .filter(|session| session.user_id == user_id && session.revoked_at.is_none())
+            .cloned()
+            .collect();
+
+        sessions.sort_by_key(|session| Reverse(session.last_seen_at));
+        Ok(sessions)
+    }
+
+    fn revoke_session(&self, session_id: &str, user_id: &str) -> RepositoryResult<bool> {
+        let mut store = lock(&self.store);
+        let Some(session) = store.sessions.iter_mut().find(|session| {
+            session.id == session_id && session.user_id == user_id && session.revoked_at.is_none()
+        }) else {
+            return Ok(false);
+        };
+
+        session.revoked_at = Some(now());
+        Ok(true)
+    }
+
+    fn revoke_other_sessions(
+        &self,
+        user_id: &str,
+        keep_session_id: &str,
+    ) -> RepositoryResult<usize> {
+        let now = now();
+        let mut revoked = 0;
+        for session in lock(&self.store).sessions.iter_mut().filter(|session| {
+            session.user_id == user_id
+                && session.id != keep_session_id
+                && session.revoked_at.is_none()
+        }) {
+            session.revoked_at = Some(now);
+            revoked += 1;
+        }
+        Ok(revoked)
+    }
+
+    fn rotate_refresh_token(
+        &self,
+        presented_hash: &str,
+        replacement_hash: &str,
+        expires_at: NaiveDateTime,
+    ) -> RepositoryResult<RefreshOutcome> {
+        let mut store = lock(&self.store);
+        let now = now();
+
+        let Some(token) = store
+            .refresh_tokens
+            .iter()
+            .find(|token| token.token_hash == presented_hash)
+        else {
+            return Ok(RefreshOutcome::Invalid);
+        };
+        let token_id = token.id.clone();
+        let session_id = token.session_id.clone();
+        let expired = token.expires_at <= now;
+        let already_used = token.used_at.is_some();
+
+        let Some(session) = store
+            .sessions
+            .iter_mut()
+            .find(|session| session.id == session_id && session.revoked_at.is_none())
+        else {
+            return Ok(RefreshOutcome::Invalid);
+        };
+        if expired {
+            return Ok(RefreshOutcome::Invalid);
+        }
+        if already_used {
+            session.revoked_at = Some(now);
+            return Ok(RefreshOutcome::Reused);
+        }
+        session.last_seen_at = now;
+        let user_id = session.user_id.clone();
+
+        if let Some(token) = store
+            .refresh_tokens
+            .iter_mut()
+            .find(|token| token.id == token_id)
+        {
+            token.used_at = Some(now);
+        }
+        store.refresh_tokens.push(RefreshToken {
+            id: uuid::Uuid::new_v4().to_string(),
+            session_id: session_id.clone(),
+            token_hash: replacement_hash.to_string(),
+            created_at: now,
+            expires_at,
+            used_at: None,
+        });
+
+        Ok(RefreshOutcome::Rotated {
+            user_id,
+            session_id,
+        })
+    }
+}
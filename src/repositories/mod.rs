@@ -0,0 +1,251 @@
+pub mod memory;
+pub mod postgres;
+
+use chrono::NaiveDateTime;
+use diesel::Queryable;
+use std::{collections::HashMap, fmt};
+
+use crate::db::models::{
+    CreatePost, CreateRefreshToken, CreateSession, CreateUser, Post, PostStatus, Role, Session,
+    Term, UpdatePost, User,
+};
+
+/// Why a repository call failed. Handlers turn it into an `ApiError`.
+#[derive(Debug)]
+pub enum RepositoryError {
+    /// A uniqueness rule or other invariant of the store would be broken.
+    Conflict(String),
+    /// The store can't be reached right now.
+    Unavailable,
+    /// Anything else; the detail is for the logs.
+    Backend(String),
+}
+
+impl fmt::Display for RepositoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepositoryError::Conflict(message) => write!(f, "{}", message),
+            RepositoryError::Unavailable => write!(f, "The store is unavailable"),
+            RepositoryError::Backend(detail) => write!(f, "{}", detail),
+        }
+    }
+}
+
+pub type RepositoryResult<T> = Result<T, RepositoryError>;
+
+/// Characters of the body a listing shows for posts without an excerpt.
+const EXCERPT_LENGTH: i32 = 200;
+
+/// Which posts a listing covers. Trashed posts are never listed.
+#[derive(Debug, Clone)]
+pub struct PostFilter {
+    pub status: PostStatus,
+    pub author: Option<String>,
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+    /// Tag slug.
+    pub tag: Option<String>,
+    /// Category slug.
+    pub category: Option<String>,
+}
+
+/// One page of a keyset scan over `(created_at, id)`.
+#[derive(Debug, Clone)]
+pub struct KeysetPage {
+    /// Only rows past this key in the scan direction.
+    pub after: Option<(NaiveDateTime, String)>,
+    pub ascending: bool,
+    pub limit: i64,
+}
+
+/// List projection of a post: everything but the full body.
+#[derive(Queryable, Debug, Clone)]
+pub struct PostListing {
+    pub id: String,
+    pub slug: String,
+    pub title: String,
+    /// The post's own excerpt, or the start of its body when it has none.
+    pub excerpt: String,
+    pub user_id: Option<String>,
+    pub status: PostStatus,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+    pub published_at: Option<NaiveDateTime>,
+    pub cover_image_url: Option<String>,
+}
+
+/// Changes to an existing post, written together or not at all.
+#[derive(Default, Debug)]
+pub struct PostEdit {
+    /// A new slug sends the current one to the slug history.
+    pub changes: UpdatePost,
+    /// Replaces the post's tags when set.
+    pub tags: Option<Vec<Term>>,
+    /// Replaces the post's categories when set.
+    pub categories: Option<Vec<Term>>,
+    /// Saves the content being overwritten as a revision by this user.
+    pub revision_by: Option<String>,
+}
+
+/// Post persistence used by the handlers. `PgPostRepository` is the real
+/// one; `InMemoryPostRepository` lets service logic run without a database.
+/// Lookups and writes skip trashed posts unless they are about the trash,
+/// while `title_exists`, `slug_taken` and `create` count them, since a
+/// trashed post keeps its title and slug until it is purged.
+pub trait PostRepository: Send + Sync {
+    fn find(&self, id: &str) -> RepositoryResult<Option<Post>>;
+
+    fn find_by_slug(&self, slug: &str) -> RepositoryResult<Option<Post>>;
+
+    /// The post that used to have `slug`.
+    fn find_by_former_slug(&self, slug: &str) -> RepositoryResult<Option<Post>>;
+
+    fn title_exists(&self, title: &str) -> RepositoryResult<bool>;
+
+    /// Whether `slug` is the current or a former slug of any post other than
+    /// `except_post_id`.
+    fn slug_taken(&self, slug: &str, except_post_id: Option<&str>) -> RepositoryResult<bool>;
+
+    /// Inserts the post and assigns its terms together; fails with a conflict
+    /// when the title or slug is taken.
+    fn create(
+        &self,
+        new_post: CreatePost,
+        tags: Option<&[Term]>,
+        categories: Option<&[Term]>,
+    ) -> RepositoryResult<Post>;
+
+    /// Tags of each of the given posts, keyed by post id and sorted by name.
+    fn tags(&self, post_ids: &[&str]) -> RepositoryResult<HashMap<String, Vec<Term>>>;
+
+    /// Categories of each of the given posts, keyed by post id and sorted by
+    /// name.
+    fn categories(&self, post_ids: &[&str]) -> RepositoryResult<HashMap<String, Vec<Term>>>;
+
+    fn count(&self, filter: &PostFilter) -> RepositoryResult<i64>;
+
+    /// Up to `page.limit` posts matching `filter`, in scan order.
+    fn list(&self, filter: &PostFilter, page: &KeysetPage) -> RepositoryResult<Vec<PostListing>>;
+
+    /// Applies `edit` if the post is still at `current`'s version and returns
+    /// the result; `None` means it changed in the meantime. Fails with a
+    /// conflict when the new title or slug is taken.
+    fn update(&self, current: &Post, edit: PostEdit) -> RepositoryResult<Option<Post>>;
+
+    /// Returns the updated post, or `None` when there is no such post.
+    fn set_status(
+        &self,
+        id: &str,
+        status: PostStatus,
+        published_at: Option<NaiveDateTime>,
+    ) -> RepositoryResult<Option<Post>>;
+
+    /// Moves the post to the trash if it is still at `expected_version`;
+    /// `false` means it changed or vanished in the meantime.
+    fn trash(&self, id: &str, expected_version: i32) -> RepositoryResult<bool>;
+
+    /// Trashed posts, most recently deleted first, limited to `author_id`'s
+    /// when given.
+    fn list_trash(&self, author_id: Option<&str>) -> RepositoryResult<Vec<Post>>;
+
+    fn find_trashed(&self, id: &str) -> RepositoryResult<Option<Post>>;
+
+    /// Takes the post out of the trash; `None` when it isn't in there.
+    fn restore(&self, id: &str) -> RepositoryResult<Option<Post>>;
+
+    /// Deletes a trashed post for good along with everything hanging off it;
+    /// `false` when it isn't in the trash.
+    fn purge(&self, id: &str) -> RepositoryResult<bool>;
+}
+
+/// What presenting a refresh token led to.
+#[derive(Debug)]
+pub enum RefreshOutcome {
+    /// The token was exchanged for the replacement, which the session now
+    /// uses.
+    Rotated { user_id: String, session_id: String },
+    /// The token had already been exchanged, so the session was revoked.
+    Reused,
+    /// The token is unknown or expired, or its session is revoked.
+    Invalid,
+}
+
+/// User, session and refresh token persistence used by the handlers, with
+/// the same Postgres and in-memory split as `PostRepository`.
+pub trait UserRepository: Send + Sync {
+    fn find(&self, id: &str) -> RepositoryResult<Option<User>>;
+
+    fn find_by_email(&self, email: &str) -> RepositoryResult<Option<User>>;
+
+    /// Names of the given users, keyed by user id.
+    fn names(&self, ids: &[&str]) -> RepositoryResult<HashMap<String, String>>;
+
+    /// Fails with a conflict when the email is already registered.
+    fn create(&self, new_user: CreateUser) -> RepositoryResult<User>;
+
+    /// Every user, oldest first.
+    fn list(&self) -> RepositoryResult<Vec<User>>;
+
+    /// Returns the updated user, or `None` when there is no such user. Fails
+    /// with a conflict when it would demote the only admin.
+    fn update_role(&self, id: &str, role: Role) -> RepositoryResult<Option<User>>;
+
+    fn mark_verified(&self, id: &str) -> RepositoryResult<()>;
+
+    /// Records a verification email as sent at `now`, unless one already went
+    /// out after `cooldown_start`. Claiming is atomic, so concurrent resends
+    /// can't both pass; `false` means the user is still cooling down.
+    fn claim_verification_resend(
+        &self,
+        id: &str,
+        cooldown_start: NaiveDateTime,
+        now: NaiveDateTime,
+    ) -> RepositoryResult<bool>;
+
+    /// Sets a new password and revokes every session of the user, unless the
+    /// password has changed since `issued_at`. That makes each reset token
+    /// single-use; `false` means it was already used or the user is gone.
+    fn reset_password(
+        &self,
+        id: &str,
+        hashed_password: String,
+        issued_at: NaiveDateTime,
+    ) -> RepositoryResult<bool>;
+
+    /// Starts a session together with its first refresh token.
+    fn create_session(
+        &self,
+        session: CreateSession,
+        refresh_token: CreateRefreshToken,
+    ) -> RepositoryResult<()>;
+
+    /// Marks an active session as seen and returns its user's current role.
+    /// `None` means the session is unknown, belongs to another user or has
+    /// been revoked.
+    fn touch_session(&self, session_id: &str, user_id: &str) -> RepositoryResult<Option<Role>>;
+
+    /// The user's sessions that haven't been revoked, most recently seen
+    /// first.
+    fn active_sessions(&self, user_id: &str) -> RepositoryResult<Vec<Session>>;
+
+    /// `false` when it isn't an active session of the user.
+    fn revoke_session(&self, session_id: &str, user_id: &str) -> RepositoryResult<bool>;
+
+    /// Revokes every active session of the user but `keep_session_id` and
+    /// returns how many there were.
+    fn revoke_other_sessions(
+        &self,
+        user_id: &str,
+        keep_session_id: &str,
+    ) -> RepositoryResult<usize>;
+
+    /// Exchanges the refresh token hashing to `presented_hash` for one hashing
+    /// to `replacement_hash`. Each token may be exchanged exactly once;
+    /// presenting one again means it leaked, so its whole session is revoked.
+    fn rotate_refresh_token(
+        &self,
+        presented_hash: &str,
+        replacement_hash: &str,
+        expires_at: NaiveDateTime,
+    ) -> RepositoryResult<RefreshOutcome>;
+}
@@ -0,0 +1,783 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel::{
+    define_sql_function,
+    dsl::exists,
+    pg::Pg,
+    r2d2::PoolError,
+    result::{DatabaseErrorKind, Error as DieselError, Error::DatabaseError},
+    sql_types::{Integer, Nullable, Text},
+    BoolExpressionMethods, Connection, ExpressionMethods, OptionalExtension, PgConnection,
+    QueryDsl, QueryResult, RunQueryDsl, SelectableHelper,
+};
+use std::collections::HashMap;
+
+use super::{
+    KeysetPage, PostEdit, PostFilter, PostListing, PostRepository, RefreshOutcome, RepositoryError,
+    RepositoryResult, UserRepository, EXCERPT_LENGTH,
+};
+use crate::db::{
+    connection::DbPool,
+    models::{
+        CreateCategory, CreatePost, CreatePostRevision, CreateRefreshToken, CreateSession,
+        CreateTag, CreateUser, Post, PostStatus, RefreshToken, Role, Session, Term, User,
+    },
+    schema::{
+        categories, post_categories, post_revisions, post_slug_history, post_tags, posts,
+        refresh_tokens, sessions, tags, users,
+    },
+};
+
+define_sql_function!(fn left(string: Text, n: Integer) -> Text);
+define_sql_function!(fn coalesce(value: Nullable<Text>, fallback: Text) -> Text);
+
+impl From<DieselError> for RepositoryError {
+    fn from(error: DieselError) -> Self {
+        match error {
+            DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                RepositoryError::Conflict("Resource already exists".into())
+            }
+            DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _) => RepositoryError::Conflict(
+                "Resource is referenced by or refers to a missing record".into(),
+            ),
+            error => RepositoryError::Backend(format!("Database error: {}", error)),
+        }
+    }
+}
+
+impl From<PoolError> for RepositoryError {
+    fn from(_: PoolError) -> Self {
+        RepositoryError::Unavailable
+    }
+}
+
+/// Replaces the post's tags, creating any that don't exist yet.
+fn assign_tags(conn: &mut PgConnection, post_id: &str, terms: &[Term]) -> QueryResult<()> {
+    diesel::delete(post_tags::table.filter(post_tags::post_id.eq(post_id))).execute(conn)?;
+    if terms.is_empty() {
+        return Ok(());
+    }
+
+    let new_tags: Vec<CreateTag> = terms
+        .iter()
+        .map(|term| CreateTag::new(term.name.clone(), term.slug.clone()))
+        .collect();
+    diesel::insert_into(tags::table)
+        .values(new_tags)
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+
+    let tag_ids = tags::table
+        .filter(tags::slug.eq_any(terms.iter().map(|term| &term.slug)))
+        .select(tags::id)
+        .load::<String>(conn)?;
+    let rows: Vec<_> = tag_ids
+        .iter()
+        .map(|tag_id| (post_tags::post_id.eq(post_id), post_tags::tag_id.eq(tag_id)))
+        .collect();
+
+    diesel::insert_into(post_tags::table)
+        .values(rows)
+        .execute(conn)
+        .map(|_| ())
+}
+
+/// Replaces the post's categories, creating any that don't exist yet.
+fn assign_categories(conn: &mut PgConnection, post_id: &str, terms: &[Term]) -> QueryResult<()> {
+    diesel::delete(post_categories::table.filter(post_categories::post_id.eq(post_id)))
+        .execute(conn)?;
+    if terms.is_empty() {
+        return Ok(());
+    }
+
+    let new_categories: Vec<CreateCategory> = terms
+        .iter()
+        .map(|term| CreateCategory::new(term.name.clone(), term.slug.clone()))
+        .collect();
+    diesel::insert_into(categories::table)
+        .values(new_categories)
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+
+    let category_ids = categories::table
+        .filter(categories::slug.eq_any(terms.iter().map(|term| &term.slug)))
+        .select(categories::id)
+        .load::<String>(conn)?;
+    let rows: Vec<_> = category_ids
+        .iter()
+        .map(|category_id| {
+            (
+                post_categories::post_id.eq(post_id),
+                post_categories::category_id.eq(category_id),
+            )
+        })
+        .collect();
+
+    diesel::insert_into(post_categories::table)
+        .values(rows)
+        .execute(conn)
+        .map(|_| ())
+}
+
+fn group_by_post(rows: Vec<(String, String, String)>) -> HashMap<String, Vec<Term>> {
+    let mut terms: HashMap<String, Vec<Term>> = HashMap::new();
+    for (post_id, name, slug) in rows {
+        terms.entry(post_id).or_default().push(Term { name, slug });
+    }
+    terms
+}
+
+/// Saves the post's current content as a revision before it is overwritten.
+pub fn snapshot_revision(conn: &mut PgConnection, post: &Post, editor_id: &str) -> QueryResult<()> {
+    diesel::insert_into(post_revisions::table)
+        .values(CreatePostRevision::new(post, editor_id.to_string()))
+        .execute(conn)
+        .map(|_| ())
+}
+
+/// Moves the post's current slug to the history so existing links redirect;
+/// reclaiming a former slug takes it back out.
+fn retire_slug(conn: &mut PgConnection, post: &Post, new_slug: &str) -> QueryResult<()> {
+    diesel::delete(post_slug_history::table.filter(post_slug_history::slug.eq(new_slug)))
+        .execute(conn)?;
+
+    diesel::insert_into(post_slug_history::table)
+        .values((
+            post_slug_history::slug.eq(&post.slug),
+            post_slug_history::post_id.eq(&post.id),
+        ))
+        .on_conflict(post_slug_history::slug)
+        .do_update()
+        .set(post_slug_history::post_id.eq(&post.id))
+        .execute(conn)
+        .map(|_| ())
+}
+
+fn filter_posts<'a>(filter: &'a PostFilter) -> posts::BoxedQuery<'a, Pg> {
+    let mut query = posts::table
+        .filter(posts::status.eq(filter.status))
+        .filter(posts::deleted_at.is_null())
+        .into_boxed();
+
+    if let Some(author) = &filter.author {
+        query = query.filter(posts::user_id.eq(author));
+    }
+    if let Some(from) = filter.from {
+        query = query.filter(posts::created_at.ge(from));
+    }
+    if let Some(to) = filter.to {
+        query = query.filter(posts::created_at.le(to));
+    }
+    if let Some(tag) = &filter.tag {
+        query = query.filter(
+            posts::id.eq_any(
+                post_tags::table
+                    .inner_join(tags::table)
+                    .filter(tags::slug.eq(tag))
+                    .select(post_tags::post_id),
+            ),
+        );
+    }
+    if let Some(category) = &filter.category {
+        query = query.filter(
+            posts::id.eq_any(
+                post_categories::table
+                    .inner_join(categories::table)
+                    .filter(categories::slug.eq(category))
+                    .select(post_categories::post_id),
+            ),
+        );
+    }
+
+    query
+}
+
+pub struct PgPostRepository {
+    pool: DbPool,
+}
+
+impl PgPostRepository {
+    pub fn new(pool: DbPool) -> Self {
+        PgPostRepository { pool }
+    }
+}
+
+impl PostRepository for PgPostRepository {
+    fn find(&self, id: &str) -> RepositoryResult<Option<Post>> {
+        let mut conn = self.pool.get()?;
+        Ok(posts::table
+            .find(id)
+            .filter(posts::deleted_at.is_null())
+            .select(Post::as_select())
+            .first::<Post>(&mut conn)
+            .optional()?)
+    }
+
+    fn find_by_slug(&self, slug: &str) -> RepositoryResult<Option<Post>> {
+        let mut conn = self.pool.get()?;
+        Ok(posts::table
+            .filter(posts::slug.eq(slug))
+            .filter(posts::deleted_at.is_null())
+            .select(Post::as_select())
+            .first::<Post>(&mut conn)
+            .optional()?)
+    }
+
+    fn find_by_former_slug(&self, slug: &str) -> RepositoryResult<Option<Post>> {
+        let mut conn = self.pool.get()?;
+        Ok(post_slug_history::table
+            .inner_join(posts::table)
+            .filter(post_slug_history::slug.eq(slug))
+            .filter(posts::deleted_at.is_null())
+            .select(Post::as_select())
+            .first::<Post>(&mut conn)
+            .optional()?)
+    }
+
+    fn title_exists(&self, title: &str) -> RepositoryResult<bool> {
+        let mut conn = self.pool.get()?;
+        Ok(
+            diesel::select(exists(posts::table.filter(posts::title.eq(title))))
+                .get_result::<bool>(&mut conn)?,
+        )
+    }
+
+    fn slug_taken(&self, slug: &str, except_post_id: Option<&str>) -> RepositoryResult<bool> {
+        let mut conn = self.pool.get()?;
+        let except_post_id = except_post_id.unwrap_or_default();
+
+        let in_posts = diesel::select(exists(
+            posts::table
+                .filter(posts::slug.eq(slug))
+                .filter(posts::id.ne(except_post_id)),
+        ))
+        .get_result::<bool>(&mut conn)?;
+        if in_posts {
+            return Ok(true);
+        }
+
+        Ok(diesel::select(exists(
+            post_slug_history::table
+                .filter(post_slug_history::slug.eq(slug))
+                .filter(post_slug_history::post_id.ne(except_post_id)),
+        ))
+        .get_result::<bool>(&mut conn)?)
+    }
+
+    fn create(
+        &self,
+        new_post: CreatePost,
+        tags: Option<&[Term]>,
+        categories: Option<&[Term]>,
+    ) -> RepositoryResult<Post> {
+        let mut conn = self.pool.get()?;
+
+        let created_post = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            let post = diesel::insert_into(posts::table)
+                .values(new_post)
+                .returning(Post::as_returning())
+                .get_result::<Post>(conn)?;
+
+            if let Some(tags) = tags {
+                assign_tags(conn, &post.id, tags)?;
+            }
+            if let Some(categories) = categories {
+                assign_categories(conn, &post.id, categories)?;
+            }
+
+            Ok(post)
+        });
+
+        created_post.map_err(|e| match e {
+            DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                RepositoryError::Conflict("Title or slug already exists".into())
+            }
+            e => e.into(),
+        })
+    }
+
+    fn tags(&self, post_ids: &[&str]) -> RepositoryResult<HashMap<String, Vec<Term>>> {
+        let mut conn = self.pool.get()?;
+        let rows = post_tags::table
+            .inner_join(tags::table)
+            .filter(post_tags::post_id.eq_any(post_ids))
+            .order(tags::name.asc())
+            .select((post_tags::post_id, tags::name, tags::slug))
+            .load::<(String, String, String)>(&mut conn)?;
+
+        Ok(group_by_post(rows))
+    }
+
+    fn categories(&self, post_ids: &[&str]) -> RepositoryResult<HashMap<String, Vec<Term>>> {
+        let mut conn = self.pool.get()?;
+        let rows = post_categories::table
+            .inner_join(categories::table)
+            .filter(post_categories::post_id.eq_any(post_ids))
+            .order(categories::name.asc())
+            .select((post_categories::post_id, categories::name, categories::slug))
+            .load::<(String, String, String)>(&mut conn)?;
+
+        Ok(group_by_post(rows))
+    }
+
+    fn count(&self, filter: &PostFilter) -> RepositoryResult<i64> {
+        let mut conn = self.pool.get()?;
+        Ok(filter_posts(filter).count().get_result::<i64>(&mut conn)?)
+    }
+
+    fn list(&self, filter: &PostFilter, page: &KeysetPage) -> RepositoryResult<Vec<PostListing>> {
+        let mut conn = self.pool.get()?;
+        let mut query = filter_posts(filter);
+
+        if let Some((cursor_created_at, cursor_id)) = &page.after {
+            query = if page.ascending {
+                query.filter(
+                    posts::created_at.gt(cursor_created_at).or(posts::created_at
+                        .eq(cursor_created_at)
+                        .and(posts::id.gt(cursor_id))),
+                )
+            } else {
+                query.filter(
+                    posts::created_at.lt(cursor_created_at).or(posts::created_at
+                        .eq(cursor_created_at)
+                        .and(posts::id.lt(cursor_id))),
+                )
+            };
+        }
+
+        query = if page.ascending {
+            query.order((posts::created_at.asc(), posts::id.asc()))
+        } else {
+            query.order((posts::created_at.desc(), posts::id.desc()))
+        };
+
+        Ok(query
+            .select((
+                posts::id,
+                posts::slug,
+                posts::title,
+                coalesce(posts::excerpt, left(posts::body, EXCERPT_LENGTH)),
+                posts::user_id,
+                posts::status,
+                posts::created_at,
+                posts::updated_at,
+                posts::published_at,
+                posts::cover_image_url,
+            ))
+            .limit(page.limit)
+            .load::<PostListing>(&mut conn)?)
+    }
+
+    fn update(&self, current: &Post, edit: PostEdit) -> RepositoryResult<Option<Post>> {
+        let mut conn = self.pool.get()?;
+
+        let updated_post = conn.transaction::<_, DieselError, _>(|conn| {
+            if let Some(editor_id) = &edit.revision_by {
+                snapshot_revision(conn, current, editor_id)?;
+            }
+            if let Some(new_slug) = edit.changes.slug.as_deref() {
+                if new_slug != current.slug {
+                    retire_slug(conn, current, new_slug)?;
+                }
+            }
+            if let Some(tags) = &edit.tags {
+                assign_tags(conn, &current.id, tags)?;
+            }
+            if let Some(categories) = &edit.categories {
+                assign_categories(conn, &current.id, categories)?;
+            }
+
+            // Matching on the version the caller read makes its checks atomic
+            // with the write; a miss rolls the rest back.
+            diesel::update(
+                posts::table
+                    .find(&current.id)
+                    .filter(posts::version.eq(current.version)),
+            )
+            .set((&edit.changes, posts::version.eq(posts::version + 1)))
+            .returning(Post::as_returning())
+            .get_result::<Post>(conn)
+        });
+
+        match updated_post {
+            Ok(post) => Ok(Some(post)),
+            Err(DieselError::NotFound) => Ok(None),
+            Err(DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => Err(
+                RepositoryError::Conflict("Title or slug already exists".into()),
+            ),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn set_status(
+        &self,
+        id: &str,
+        status: PostStatus,
+        published_at: Option<NaiveDateTime>,
+    ) -> RepositoryResult<Option<Post>> {
+        let mut conn = self.pool.get()?;
+        Ok(
+            diesel::update(posts::table.find(id).filter(posts::deleted_at.is_null()))
+                .set((
+                    posts::status.eq(status),
+                    posts::published_at.eq(published_at),
+                    posts::version.eq(posts::version + 1),
+                ))
+                .returning(Post::as_returning())
+                .get_result::<Post>(&mut conn)
+                .optional()?,
+        )
+    }
+
+    fn trash(&self, id: &str, expected_version: i32) -> RepositoryResult<bool> {
+        let mut conn = self.pool.get()?;
+        let trashed = diesel::update(
+            posts::table
+                .find(id)
+                .filter(posts::deleted_at.is_null())
+                .filter(posts::version.eq(expected_version)),
+        )
+        .set((
+            posts::deleted_at.eq(Utc::now().naive_utc()),
+            posts::version.eq(posts::version + 1),
+        ))
+        .execute(&mut conn)?;
+
+        Ok(trashed > 0)
+    }
+
+    fn list_trash(&self, author_id: Option<&str>) -> RepositoryResult<Vec<Post>> {
+        let mut conn = self.pool.get()?;
+        let mut query = posts::table
+            .filter(posts::deleted_at.is_not_null())
+            .order(posts::deleted_at.desc())
+            .select(Post::as_select())
+            .into_boxed();
+        if let Some(author_id) = author_id {
+            query = query.filter(posts::user_id.eq(author_id));
+        }
+
+        Ok(query.load::<Post>(&mut conn)?)
+    }
+
+    fn find_trashed(&self, id: &str) -> RepositoryResult<Option<Post>> {
+        let mut conn = self.pool.get()?;
+        Ok(posts::table
+            .find(id)
+            .filter(posts::deleted_at.is_not_null())
+            .select(Post::as_select())
+            .first::<Post>(&mut conn)
+            .optional()?)
+    }
+
+    fn restore(&self, id: &str) -> RepositoryResult<Option<Post>> {
+        let mut conn = self.pool.get()?;
+        Ok(diesel::update(
+            posts::table
+                .find(id)
+                .filter(posts::deleted_at.is_not_null()),
+        )
+        .set((
+            posts::deleted_at.eq(None::<NaiveDateTime>),
+            posts::version.eq(posts::version + 1),
+        ))
+        .returning(Post::as_returning())
+        .get_result::<Post>(&mut conn)
+        .optional()?)
+    }
+
+    fn purge(&self, id: &str) -> RepositoryResult<bool> {
+        let mut conn = self.pool.get()?;
+        let purged = diesel::delete(
+            posts::table
+                .find(id)
+                .filter(posts::deleted_at.is_not_null()),
+        )
+        .execute(&mut conn)?;
+
+        Ok(purged > 0)
+    }
+}
+
+pub struct PgUserRepository {
+    pool: DbPool,
+}
+
+impl PgUserRepository {
+    pub fn new(pool: DbPool) -> Self {
+        PgUserRepository { pool }
+    }
+}
+
+impl UserRepository for PgUserRepository {
+    fn find(&self, id: &str) -> RepositoryResult<Option<User>> {
+        let mut conn = self.pool.get()?;
+        Ok(users::table
+            .find(id)
+            .select(User::as_select())
+            .first::<User>(&mut conn)
+            .optional()?)
+    }
+
+    fn find_by_email(&self, email: &str) -> RepositoryResult<Option<User>> {
+        let mut conn = self.pool.get()?;
+        Ok(users::table
+            .filter(users::email.eq(email))
+            .select(User::as_select())
+            .first::<User>(&mut conn)
+            .optional()?)
+    }
+
+    fn names(&self, ids: &[&str]) -> RepositoryResult<HashMap<String, String>> {
+        let mut conn = self.pool.get()?;
+        Ok(users::table
+            .filter(users::id.eq_any(ids))
+            .select((users::id, users::name))
+            .load::<(String, String)>(&mut conn)?
+            .into_iter()
+            .collect())
+    }
+
+    fn create(&self, new_user: CreateUser) -> RepositoryResult<User> {
+        let mut conn = self.pool.get()?;
+        diesel::insert_into(users::table)
+            .values(new_user)
+            .returning(User::as_returning())
+            .get_result::<User>(&mut conn)
+            .map_err(|e| match e {
+                DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                    RepositoryError::Conflict("User already exists".into())
+                }
+                e => e.into(),
+            })
+    }
+
+    fn list(&self) -> RepositoryResult<Vec<User>> {
+        let mut conn = self.pool.get()?;
+        Ok(users::table
+            .order(users::created_at.asc())
+            .select(User::as_select())
+            .load::<User>(&mut conn)?)
+    }
+
+    fn update_role(&self, id: &str, role: Role) -> RepositoryResult<Option<User>> {
+        let mut conn = self.pool.get()?;
+        conn.transaction(|conn| {
+            // Locking the admins queues concurrent demotions behind each
+            // other, so two admins can't demote one another and leave none.
+            let admins = users::table
+                .filter(users::role.eq(Role::Admin))
+                .select(users::id)
+                .for_update()
+                .load::<String>(conn)?;
+            if role != Role::Admin && admins == [id] {
+                return Err(RepositoryError::Conflict(
+                    "The last admin cannot be demoted".into(),
+                ));
+            }
+
+            Ok(diesel::update(users::table.find(id))
+                .set(users::role.eq(role))
+                .returning(User::as_returning())
+                .get_result::<User>(conn)
+                .optional()?)
+        })
+    }
+
+    fn mark_verified(&self, id: &str) -> RepositoryResult<()> {
+        let mut conn = self.pool.get()?;
+        diesel::update(users::table.find(id))
+            .set(users::verified.eq(true))
+            .execute(&mut conn)?;
+        Ok(())
+    }
+
+    fn claim_verification_resend(
+        &self,
+        id: &str,
+        cooldown_start: NaiveDateTime,
+        now: NaiveDateTime,
+    ) -> RepositoryResult<bool> {
+        let mut conn = self.pool.get()?;
+        let claimed = diesel::update(
+            users::table.find(id).filter(
+                users::verification_sent_at
+                    .is_null()
+                    .or(users::verification_sent_at.lt(cooldown_start)),
+            ),
+        )
+        .set(users::verification_sent_at.eq(now))
+        .execute(&mut conn)?;
+
+        Ok(claimed > 0)
+    }
+
+    fn reset_password(
+        &self,
+        id: &str,
+        hashed_password: String,
+        issued_at: NaiveDateTime,
+    ) -> RepositoryResult<bool> {
+        let mut conn = self.pool.get()?;
+        Ok(conn.transaction::<_, DieselError, _>(|conn| {
+            let now = Utc::now().naive_utc();
+            let updated = diesel::update(
+                users::table.find(id).filter(
+                    users::password_changed_at
+                        .is_null()
+                        .or(users::password_changed_at.lt(issued_at)),
+                ),
+            )
+            .set((
+                users::password.eq(hashed_password),
+                users::password_changed_at.eq(now),
+            ))
+            .execute(conn)?;
+
+            if updated > 0 {
+                diesel::update(
+                    sessions::table
+                        .filter(sessions::user_id.eq(id))
+                        .filter(sessions::revoked_at.is_null()),
+                )
+                .set(sessions::revoked_at.eq(now))
+                .execute(conn)?;
+            }
+
+            Ok(updated > 0)
+        })?)
+    }
+
+    fn create_session(
+        &self,
+        session: CreateSession,
+        refresh_token: CreateRefreshToken,
+    ) -> RepositoryResult<()> {
+        let mut conn = self.pool.get()?;
+        Ok(conn.transaction::<_, DieselError, _>(|conn| {
+            diesel::insert_into(sessions::table)
+                .values(&session)
+                .execute(conn)?;
+            diesel::insert_into(refresh_tokens::table)
+                .values(&refresh_token)
+                .execute(conn)?;
+            Ok(())
+        })?)
+    }
+
+    fn touch_session(&self, session_id: &str, user_id: &str) -> RepositoryResult<Option<Role>> {
+        let mut conn = self.pool.get()?;
+        let touched = diesel::update(
+            sessions::table
+                .find(session_id)
+                .filter(sessions::user_id.eq(user_id))
+                .filter(sessions::revoked_at.is_null()),
+        )
+        .set(sessions::last_seen_at.eq(Utc::now().naive_utc()))
+        .execute(&mut conn)?;
+        if touched == 0 {
+            return Ok(None);
+        }
+
+        Ok(users::table
+            .find(user_id)
+            .select(users::role)
+            .first::<Role>(&mut conn)
+            .optional()?)
+    }
+
+    fn active_sessions(&self, user_id: &str) -> RepositoryResult<Vec<Session>> {
+        let mut conn = self.pool.get()?;
+        Ok(sessions::table
+            .filter(sessions::user_id.eq(user_id))
+            .filter(sessions::revoked_at.is_null())
+            .order(sessions::last_seen_at.desc())
+            .select(Session::as_select())
+            .load::<Session>(&mut conn)?)
+    }
+
+    fn revoke_session(&self, session_id: &str, user_id: &str) -> RepositoryResult<bool> {
+        let mut conn = self.pool.get()?;
+        let revoked = diesel::update(
+            sessions::table
+                .find(session_id)
+                .filter(sessions::user_id.eq(user_id))
+                .filter(sessions::revoked_at.is_null()),
+        )
+        .set(sessions::revoked_at.eq(Utc::now().naive_utc()))
+        .execute(&mut conn)?;
+
+        Ok(revoked > 0)
+    }
+
+    fn revoke_other_sessions(
+        &self,
+        user_id: &str,
+        keep_session_id: &str,
+    ) -> RepositoryResult<usize> {
+        let mut conn = self.pool.get()?;
+        Ok(diesel::update(
+            sessions::table
+                .filter(sessions::user_id.eq(user_id))
+                .filter(sessions::id.ne(keep_session_id))
+                .filter(sessions::revoked_at.is_null()),
+        )
+        .set(sessions::revoked_at.eq(Utc::now().naive_utc()))
+        .execute(&mut conn)?)
+    }
+
+    fn rotate_refresh_token(
+        &self,
+        presented_hash: &str,
+        replacement_hash: &str,
+        expires_at: NaiveDateTime,
+    ) -> RepositoryResult<RefreshOutcome> {
+        let mut conn = self.pool.get()?;
+        Ok(conn.transaction::<_, DieselError, _>(|conn| {
+            let now = Utc::now().naive_utc();
+            let existing = refresh_tokens::table
+                .inner_join(sessions::table)
+                .filter(refresh_tokens::token_hash.eq(presented_hash))
+                .select((RefreshToken::as_select(), Session::as_select()))
+                .first::<(RefreshToken, Session)>(conn)
+                .optional()?;
+
+            let Some((token, session)) = existing else {
+                return Ok(RefreshOutcome::Invalid);
+            };
+            if session.revoked_at.is_some() || token.expires_at <= now {
+                return Ok(RefreshOutcome::Invalid);
+            }
+
+            let claimed = diesel::update(
+                refresh_tokens::table
+                    .find(&token.id)
+                    .filter(refresh_tokens::used_at.is_null()),
+            )
+            .set(refresh_tokens::used_at.eq(now))
+            .execute(conn)?;
+
+            if claimed == 0 {
+                diesel::update(sessions::table.find(&session.id))
+                    .set(sessions::revoked_at.eq(now))
+                    .execute(conn)?;
+                return Ok(RefreshOutcome::Reused);
+            }
+
+            diesel::insert_into(refresh_tokens::table)
+                .values(CreateRefreshToken::new(
+                    session.id.clone(),
+                    replacement_hash.to_string(),
+                    expires_at,
+                ))
+                .execute(conn)?;
+            diesel::update(sessions::table.find(&session.id))
+                .set(sessions::last_seen_at.eq(now))
+                .execute(conn)?;
+
+            Ok(RefreshOutcome::Rotated {
+                user_id: session.user_id,
+                session_id: session.id,
+            })
+        })?)
+    }
+}
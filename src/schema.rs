@@ -1,14 +1,152 @@
 // @generated automatically by Diesel CLI.
 
+pub mod sql_types {
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "tsvector", schema = "pg_catalog"))]
+    pub struct Tsvector;
+}
+
+diesel::table! {
+    categories (id) {
+        id -> Text,
+        name -> Varchar,
+        slug -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
+    comments (id) {
+        id -> Text,
+        post_id -> Text,
+        user_id -> Nullable<Text>,
+        guest_name -> Nullable<Varchar>,
+        guest_email -> Nullable<Text>,
+        parent_id -> Nullable<Text>,
+        body -> Text,
+        status -> Varchar,
+        created_at -> Timestamp,
+        updated_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    media (id) {
+        id -> Text,
+        user_id -> Nullable<Text>,
+        storage_key -> Text,
+        file_name -> Varchar,
+        content_type -> Varchar,
+        size_bytes -> Int8,
+        width -> Nullable<Int4>,
+        height -> Nullable<Int4>,
+        checksum -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    media_variants (id) {
+        id -> Text,
+        media_id -> Text,
+        name -> Varchar,
+        content_type -> Varchar,
+        storage_key -> Text,
+        width -> Int4,
+        height -> Int4,
+        size_bytes -> Int8,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    post_categories (post_id, category_id) {
+        post_id -> Text,
+        category_id -> Text,
+    }
+}
+
+diesel::table! {
+    post_revisions (id) {
+        id -> Text,
+        post_id -> Text,
+        editor_id -> Nullable<Text>,
+        title -> Varchar,
+        body -> Text,
+        body_format -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    post_slug_history (slug) {
+        slug -> Varchar,
+        post_id -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    post_tags (post_id, tag_id) {
+        post_id -> Text,
+        tag_id -> Text,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Tsvector;
+
     posts (id) {
         id -> Text,
         title -> Varchar,
         body -> Text,
-        published -> Bool,
         user_id -> Nullable<Text>,
         created_at -> Timestamp,
         updated_at -> Nullable<Timestamp>,
+        status -> Varchar,
+        published_at -> Nullable<Timestamp>,
+        slug -> Varchar,
+        body_format -> Varchar,
+        rendered_body -> Nullable<Text>,
+        search_vector -> Tsvector,
+        version -> Int4,
+        excerpt -> Nullable<Text>,
+        cover_image_url -> Nullable<Text>,
+        deleted_at -> Nullable<Timestamp>,
+        cover_media_id -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    refresh_tokens (id) {
+        id -> Text,
+        session_id -> Text,
+        token_hash -> Text,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    sessions (id) {
+        id -> Text,
+        user_id -> Text,
+        created_at -> Timestamp,
+        last_seen_at -> Timestamp,
+        user_agent -> Nullable<Text>,
+        ip_address -> Nullable<Text>,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    tags (id) {
+        id -> Text,
+        name -> Varchar,
+        slug -> Varchar,
+        created_at -> Timestamp,
     }
 }
 
@@ -21,12 +159,40 @@ diesel::table! {
         verified -> Bool,
         created_at -> Timestamp,
         updated_at -> Nullable<Timestamp>,
+        verification_sent_at -> Nullable<Timestamp>,
+        password_changed_at -> Nullable<Timestamp>,
+        role -> Varchar,
     }
 }
 
+diesel::joinable!(comments -> posts (post_id));
+diesel::joinable!(comments -> users (user_id));
+diesel::joinable!(media -> users (user_id));
+diesel::joinable!(media_variants -> media (media_id));
+diesel::joinable!(post_categories -> categories (category_id));
+diesel::joinable!(post_categories -> posts (post_id));
+diesel::joinable!(post_revisions -> posts (post_id));
+diesel::joinable!(post_revisions -> users (editor_id));
+diesel::joinable!(post_slug_history -> posts (post_id));
+diesel::joinable!(post_tags -> posts (post_id));
+diesel::joinable!(post_tags -> tags (tag_id));
+diesel::joinable!(posts -> media (cover_media_id));
 diesel::joinable!(posts -> users (user_id));
+diesel::joinable!(refresh_tokens -> sessions (session_id));
+diesel::joinable!(sessions -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    categories,
+    comments,
+    media,
+    media_variants,
+    post_categories,
+    post_revisions,
+    post_slug_history,
+    post_tags,
     posts,
+    refresh_tokens,
+    sessions,
+    tags,
     users,
 );
@@ -0,0 +1,580 @@
+use actix_web::{
+    delete, get, post, put,
+    web::{Data, Json, Path, Query},
+    HttpResponse,
+};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::{
+    ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, QueryResult, RunQueryDsl,
+    SelectableHelper,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use validator::Validate;
+
+use crate::{
+    db::{
+        connection::AppState,
+        models::{Comment, CommentStatus, CreateComment, Post, Role},
+        schema::{comments, posts, users},
+    },
+    errors::ApiError,
+    middlewares::{
+        auth::{AuthenticatedUser, OptionalUser},
+        permissions::Permission,
+    },
+    services::posts::{can_view_post, post_not_found},
+};
+
+/// How long after posting authors may still edit a comment.
+const EDIT_WINDOW_MINUTES: i64 = 15;
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+#[derive(Deserialize, Validate, Debug)]
+pub struct CreateCommentRequest {
+    #[validate(length(
+        min = 1,
+        max = 5000,
+        message = "Comment length must be between 1 and 5000 characters"
+    ))]
+    pub body: String,
+    /// The comment being replied to.
+    pub parent_id: Option<String>,
+    /// Required when commenting without an account.
+    #[validate(length(
+        min = 1,
+        max = 80,
+        message = "Name length must be between 1 and 80 characters"
+    ))]
+    pub guest_name: Option<String>,
+    /// Required when commenting without an account. Never shown publicly.
+    #[validate(email(message = "Invalid email format"))]
+    pub guest_email: Option<String>,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+pub struct UpdateCommentRequest {
+    #[validate(length(
+        min = 1,
+        max = 5000,
+        message = "Comment length must be between 1 and 5000 characters"
+    ))]
+    pub body: String,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CommentLayout {
+    /// Top-level comments with their replies nested; pagination counts
+    /// top-level comments only.
+    #[default]
+    Tree,
+    /// Every comment in posting order.
+    Flat,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CommentListQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    #[serde(default)]
+    pub layout: CommentLayout,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ModerationQueueQuery {
+    /// Defaults to pending.
+    pub status: Option<CommentStatus>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ModerateCommentRequest {
+    pub status: CommentStatus,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CommentNode {
+    pub id: String,
+    pub post_id: String,
+    pub parent_id: Option<String>,
+    pub user_id: Option<String>,
+    pub author_name: String,
+    pub body: String,
+    pub status: CommentStatus,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replies: Option<Vec<CommentNode>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CommentPage {
+    pub comments: Vec<CommentNode>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+fn page_bounds(limit: Option<i64>, offset: Option<i64>) -> (i64, i64) {
+    (
+        limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE),
+        offset.unwrap_or(0).max(0),
+    )
+}
+
+/// The post comments are being read or written on, unless it is trashed.
+async fn find_post(data: &AppState, post_id: &str) -> Result<Option<Post>, ApiError> {
+    let post_id = post_id.to_string();
+    data.with_conn(move |conn| {
+        posts::table
+            .find(post_id)
+            .filter(posts::deleted_at.is_null())
+            .select(Post::as_select())
+            .first::<Post>(conn)
+            .optional()
+    })
+    .await
+}
+
+/// Attaches display names: the account name for registered commenters, the
+/// given name for guests.
+fn to_nodes(conn: &mut PgConnection, comments: Vec<Comment>) -> QueryResult<Vec<CommentNode>> {
+    let user_ids: Vec<&String> = comments
+        .iter()
+        .filter_map(|comment| comment.user_id.as_ref())
+        .collect();
+    let names: HashMap<String, String> = users::table
+        .filter(users::id.eq_any(user_ids))
+        .select((users::id, users::name))
+        .load::<(String, String)>(conn)?
+        .into_iter()
+        .collect();
+
+    Ok(comments
+        .into_iter()
+        .map(|comment| CommentNode {
+            author_name: match (&comment.user_id, comment.guest_name) {
+                (Some(user_id), _) => names
+                    .get(user_id)
+                    .cloned()
+                    .unwrap_or_else(|| "Deleted user".into()),
+                (None, Some(guest_name)) => guest_name,
+                (None, None) => "Anonymous".into(),
+            },
+            id: comment.id,
+            post_id: comment.post_id,
+            parent_id: comment.parent_id,
+            user_id: comment.user_id,
+            body: comment.body,
+            status: comment.status,
+            created_at: comment.created_at,
+            updated_at: comment.updated_at,
+            replies: None,
+        })
+        .collect())
+}
+
+/// Nests replies under their parents. Replies whose parent isn't in `nodes`
+/// (e.g. it was rejected) are dropped along with their own replies.
+fn build_tree(nodes: Vec<CommentNode>) -> Vec<CommentNode> {
+    fn attach(
+        mut node: CommentNode,
+        children: &mut HashMap<Option<String>, Vec<CommentNode>>,
+    ) -> CommentNode {
+        let replies = children
+            .remove(&Some(node.id.clone()))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|reply| attach(reply, children))
+            .collect();
+        node.replies = Some(replies);
+        node
+    }
+
+    let mut children: HashMap<Option<String>, Vec<CommentNode>> = HashMap::new();
+    for node in nodes {
+        children
+            .entry(node.parent_id.clone())
+            .or_default()
+            .push(node);
+    }
+
+    children
+        .remove(&None)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|root| attach(root, &mut children))
+        .collect()
+}
+
+fn comment_not_found(comment_id: &str) -> ApiError {
+    ApiError::NotFound(format!("Comment with id {} not found", comment_id))
+}
+
+#[post("/posts/{post_id}/comments")]
+async fn create_comment(
+    data: Data<AppState>,
+    path: Path<String>,
+    body: Json<CreateCommentRequest>,
+    OptionalUser(user): OptionalUser,
+) -> Result<HttpResponse, ApiError> {
+    let post_id = path.into_inner();
+    let comment_data = body.into_inner();
+    comment_data.validate()?;
+
+    // Verified accounts and anyone above a reader are trusted; comments from
+    // guests and fresh unverified accounts wait for moderation.
+    let (user_id, guest_name, guest_email, status) = match &user {
+        Some(user) => {
+            let trusted = user.role != Role::Reader || {
+                let user_id = user.user_id.clone();
+                data.blocking(move |data| Ok(data.users.find(&user_id)?))
+                    .await?
+                    .is_some_and(|user| user.verified)
+            };
+            let status = if trusted {
+                CommentStatus::Approved
+            } else {
+                CommentStatus::Pending
+            };
+            (Some(user.user_id.clone()), None, None, status)
+        }
+        None => match (comment_data.guest_name, comment_data.guest_email) {
+            (Some(guest_name), Some(guest_email)) => (
+                None,
+                Some(guest_name),
+                Some(guest_email),
+                CommentStatus::Pending,
+            ),
+            _ => {
+                return Err(ApiError::BadRequest(
+                    "Guest comments need a guest_name and guest_email".into(),
+                ));
+            }
+        },
+    };
+
+    match find_post(&data, &post_id).await? {
+        Some(post) if post.is_public() => {}
+        Some(post) if can_view_post(&post, user.as_ref()) => {
+            return Err(ApiError::Forbidden(
+                "Comments are only open on published posts".into(),
+            ));
+        }
+        _ => return Err(post_not_found(&post_id)),
+    }
+
+    if let Some(parent_id) = comment_data.parent_id.clone() {
+        let parent = data
+            .with_conn({
+                let post_id = post_id.clone();
+                move |conn| {
+                    comments::table
+                        .find(parent_id)
+                        .filter(comments::post_id.eq(&post_id))
+                        .filter(comments::status.eq(CommentStatus::Approved))
+                        .select(comments::id)
+                        .first::<String>(conn)
+                        .optional()
+                }
+            })
+            .await?;
+
+        if parent.is_none() {
+            return Err(ApiError::BadRequest(
+                "The parent comment does not exist on this post".into(),
+            ));
+        }
+    }
+
+    let new_comment = CreateComment::new(
+        post_id,
+        user_id,
+        guest_name,
+        guest_email,
+        comment_data.parent_id,
+        comment_data.body,
+        status,
+    );
+
+    let comment = data
+        .with_conn(move |conn| {
+            diesel::insert_into(comments::table)
+                .values(new_comment)
+                .returning(Comment::as_returning())
+                .get_result::<Comment>(conn)
+        })
+        .await?;
+
+    let message = if comment.status == CommentStatus::Pending {
+        "Comment submitted and awaiting moderation"
+    } else {
+        "Comment successfully posted"
+    };
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "success": message,
+        "comment": comment,
+    })))
+}
+
+/// Approved comments on a post, as a tree of threads or a flat list.
+#[get("/posts/{post_id}/comments")]
+async fn get_comments(
+    data: Data<AppState>,
+    path: Path<String>,
+    query: Query<CommentListQuery>,
+    OptionalUser(user): OptionalUser,
+) -> Result<HttpResponse, ApiError> {
+    let post_id = path.into_inner();
+    let list_query = query.into_inner();
+    let (limit, offset) = page_bounds(list_query.limit, list_query.offset);
+
+    find_post(&data, &post_id)
+        .await?
+        .filter(|post| can_view_post(post, user.as_ref()))
+        .ok_or_else(|| post_not_found(&post_id))?;
+
+    let (comments, total) = data
+        .with_conn(move |conn| {
+            let approved = comments::table
+                .filter(comments::post_id.eq(&post_id))
+                .filter(comments::status.eq(CommentStatus::Approved));
+
+            match list_query.layout {
+                CommentLayout::Flat => {
+                    let total = approved.count().get_result::<i64>(conn)?;
+                    let page = approved
+                        .order((comments::created_at.asc(), comments::id.asc()))
+                        .limit(limit)
+                        .offset(offset)
+                        .select(Comment::as_select())
+                        .load::<Comment>(conn)?;
+                    Ok((to_nodes(conn, page)?, total))
+                }
+                // Threads are assembled in memory, so the whole approved set is
+                // loaded and the top-level comments paginated afterwards.
+                CommentLayout::Tree => {
+                    let all = approved
+                        .order((comments::created_at.asc(), comments::id.asc()))
+                        .select(Comment::as_select())
+                        .load::<Comment>(conn)?;
+                    let threads = build_tree(to_nodes(conn, all)?);
+                    let total = threads.len() as i64;
+                    let page = threads
+                        .into_iter()
+                        .skip(offset as usize)
+                        .take(limit as usize)
+                        .collect();
+                    Ok((page, total))
+                }
+            }
+        })
+        .await?;
+
+    Ok(HttpResponse::Ok().json(CommentPage {
+        comments,
+        total,
+        limit,
+        offset,
+    }))
+}
+
+#[put("/comments/{comment_id}")]
+async fn update_comment(
+    data: Data<AppState>,
+    path: Path<String>,
+    body: Json<UpdateCommentRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let comment_id = path.into_inner();
+    let update_data = body.into_inner();
+    update_data.validate()?;
+
+    let comment = data
+        .with_conn({
+            let comment_id = comment_id.clone();
+            move |conn| {
+                comments::table
+                    .find(comment_id)
+                    .select(Comment::as_select())
+                    .first::<Comment>(conn)
+                    .optional()
+            }
+        })
+        .await?
+        .ok_or_else(|| comment_not_found(&comment_id))?;
+
+    if comment.user_id.as_deref() != Some(user.user_id.as_str()) {
+        return Err(ApiError::Forbidden(
+            "You can only edit your own comments".into(),
+        ));
+    }
+
+    let now = Utc::now().naive_utc();
+    if now - comment.created_at > Duration::minutes(EDIT_WINDOW_MINUTES) {
+        return Err(ApiError::Forbidden(format!(
+            "Comments can only be edited within {} minutes of posting",
+            EDIT_WINDOW_MINUTES
+        )));
+    }
+
+    let updated = data
+        .with_conn(move |conn| {
+            diesel::update(comments::table.find(comment.id))
+                .set((
+                    comments::body.eq(update_data.body),
+                    comments::updated_at.eq(now),
+                ))
+                .returning(Comment::as_returning())
+                .get_result::<Comment>(conn)
+        })
+        .await?;
+
+    Ok(HttpResponse::Ok().json(updated))
+}
+
+/// Commenters can delete their own comments; the post's author and admins can
+/// delete any on it. Replies are deleted along with the comment.
+#[delete("/comments/{comment_id}")]
+async fn delete_comment(
+    data: Data<AppState>,
+    path: Path<String>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let comment_id = path.into_inner();
+
+    let (commenter_id, post_author_id) = data
+        .with_conn({
+            let comment_id = comment_id.clone();
+            move |conn| {
+                comments::table
+                    .inner_join(posts::table)
+                    .filter(comments::id.eq(comment_id))
+                    .select((comments::user_id, posts::user_id))
+                    .first::<(Option<String>, Option<String>)>(conn)
+                    .optional()
+            }
+        })
+        .await?
+        .ok_or_else(|| comment_not_found(&comment_id))?;
+
+    if commenter_id.as_deref() != Some(user.user_id.as_str()) {
+        user.authorize_owned(
+            post_author_id.as_deref(),
+            Permission::ModerateOwnPostComments,
+            Permission::ModerateAnyComment,
+            "delete this comment",
+        )?;
+    }
+
+    data.with_conn({
+        let comment_id = comment_id.clone();
+        move |conn| diesel::delete(comments::table.find(comment_id)).execute(conn)
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": format!("Comment successfully deleted with id {}", comment_id)
+    })))
+}
+
+/// Comments awaiting a decision, oldest first. Admins see every post's queue;
+/// authors see the comments on their own posts.
+#[get("/comments/moderation")]
+async fn get_moderation_queue(
+    data: Data<AppState>,
+    query: Query<ModerationQueueQuery>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let queue_query = query.into_inner();
+    let (limit, offset) = page_bounds(queue_query.limit, queue_query.offset);
+    let status = queue_query.status.unwrap_or(CommentStatus::Pending);
+
+    let only_own_posts = !user.role.can(Permission::ModerateAnyComment);
+    if only_own_posts {
+        user.authorize(Permission::ModerateOwnPostComments, "moderate comments")?;
+    }
+
+    let (comments, total) = data
+        .with_conn(move |conn| {
+            let queue = || {
+                let mut queue = comments::table
+                    .filter(comments::status.eq(status))
+                    .into_boxed();
+                if only_own_posts {
+                    queue = queue.filter(
+                        comments::post_id.eq_any(
+                            posts::table
+                                .filter(posts::user_id.eq(&user.user_id))
+                                .select(posts::id),
+                        ),
+                    );
+                }
+                queue
+            };
+
+            let total = queue().count().get_result::<i64>(conn)?;
+            let page = queue()
+                .order((comments::created_at.asc(), comments::id.asc()))
+                .limit(limit)
+                .offset(offset)
+                .select(Comment::as_select())
+                .load::<Comment>(conn)?;
+            Ok((to_nodes(conn, page)?, total))
+        })
+        .await?;
+
+    Ok(HttpResponse::Ok().json(CommentPage {
+        comments,
+        total,
+        limit,
+        offset,
+    }))
+}
+
+#[post("/comments/{comment_id}/moderate")]
+async fn moderate_comment(
+    data: Data<AppState>,
+    path: Path<String>,
+    body: Json<ModerateCommentRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let comment_id = path.into_inner();
+    let new_status = body.into_inner().status;
+
+    let post_author_id = data
+        .with_conn({
+            let comment_id = comment_id.clone();
+            move |conn| {
+                comments::table
+                    .inner_join(posts::table)
+                    .filter(comments::id.eq(comment_id))
+                    .select(posts::user_id)
+                    .first::<Option<String>>(conn)
+                    .optional()
+            }
+        })
+        .await?
+        .ok_or_else(|| comment_not_found(&comment_id))?;
+
+    user.authorize_owned(
+        post_author_id.as_deref(),
+        Permission::ModerateOwnPostComments,
+        Permission::ModerateAnyComment,
+        "moderate this comment",
+    )?;
+
+    let updated = data
+        .with_conn(move |conn| {
+            diesel::update(comments::table.find(comment_id))
+                .set(comments::status.eq(new_status))
+                .returning(Comment::as_returning())
+                .get_result::<Comment>(conn)
+        })
+        .await?;
+
+    Ok(HttpResponse::Ok().json(updated))
+}
@@ -0,0 +1,387 @@
+use actix_web::{
+    get,
+    http::header::{
+        CacheControl, CacheDirective, ETag, EntityTag, Header, HttpDate, IfNoneMatch, LastModified,
+    },
+    web::{Data, Path},
+    HttpRequest, HttpResponse,
+};
+use chrono::{NaiveDateTime, SecondsFormat};
+use diesel::{
+    ExpressionMethods, NullableExpressionMethods, OptionalExtension, PgConnection,
+    PgSortExpressionMethods, QueryDsl, QueryResult, RunQueryDsl, SelectableHelper,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use crate::{
+    db::{
+        connection::AppState,
+        models::{Post, PostStatus},
+        schema::{post_tags, posts, tags, users},
+    },
+    errors::ApiError,
+    utils::{
+        render::{escape_html, render_body},
+        site::{api_url, post_url, site_title, site_url},
+    },
+};
+
+const FEED_SIZE: i64 = 20;
+/// Seconds feed readers may cache a feed before asking again.
+const FEED_MAX_AGE: u32 = 300;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedFormat {
+    Rss,
+    Atom,
+}
+
+/// Which published posts a feed carries.
+enum FeedScope {
+    All,
+    Author { id: String, name: String },
+    Tag { slug: String, name: String },
+}
+
+impl FeedScope {
+    fn title(&self) -> String {
+        match self {
+            FeedScope::All => site_title(),
+            FeedScope::Author { name, .. } => format!("{}: posts by {}", site_title(), name),
+            FeedScope::Tag { name, .. } => format!("{}: posts tagged {}", site_title(), name),
+        }
+    }
+}
+
+struct FeedEntry {
+    post: Post,
+    author_name: Option<String>,
+    tags: Vec<String>,
+}
+
+impl FeedEntry {
+    fn published(&self) -> NaiveDateTime {
+        self.post.published_at.unwrap_or(self.post.created_at)
+    }
+
+    fn updated(&self) -> NaiveDateTime {
+        self.post.updated_at.unwrap_or_else(|| self.published())
+    }
+
+    fn content(&self) -> String {
+        self.post
+            .rendered_body
+            .clone()
+            .unwrap_or_else(|| render_body(&self.post.body, self.post.body_format))
+    }
+}
+
+fn load_entries(conn: &mut PgConnection, scope: &FeedScope) -> QueryResult<Vec<FeedEntry>> {
+    let mut query = posts::table
+        .left_join(users::table)
+        .filter(posts::status.eq(PostStatus::Published))
+        .filter(posts::deleted_at.is_null())
+        .order((
+            posts::published_at.desc().nulls_last(),
+            posts::created_at.desc(),
+        ))
+        .limit(FEED_SIZE)
+        .select((Post::as_select(), users::name.nullable()))
+        .into_boxed();
+
+    match scope {
+        FeedScope::All => {}
+        FeedScope::Author { id, .. } => {
+            query = query.filter(posts::user_id.eq(id.clone()));
+        }
+        FeedScope::Tag { slug, .. } => {
+            query = query.filter(
+                posts::id.eq_any(
+                    post_tags::table
+                        .inner_join(tags::table)
+                        .filter(tags::slug.eq(slug.clone()))
+                        .select(post_tags::post_id),
+                ),
+            );
+        }
+    }
+
+    let rows = query.load::<(Post, Option<String>)>(conn)?;
+
+    let post_ids: Vec<&str> = rows.iter().map(|(post, _)| post.id.as_str()).collect();
+    let mut tags_by_post: HashMap<String, Vec<String>> = HashMap::new();
+    for (post_id, tag_name) in post_tags::table
+        .inner_join(tags::table)
+        .filter(post_tags::post_id.eq_any(&post_ids))
+        .order(tags::name.asc())
+        .select((post_tags::post_id, tags::name))
+        .load::<(String, String)>(conn)?
+    {
+        tags_by_post.entry(post_id).or_default().push(tag_name);
+    }
+
+    Ok(rows
+        .into_iter()
+        .map(|(post, author_name)| FeedEntry {
+            tags: tags_by_post.remove(&post.id).unwrap_or_default(),
+            post,
+            author_name,
+        })
+        .collect())
+}
+
+fn rss_feed(
+    scope: &FeedScope,
+    self_url: &str,
+    updated: NaiveDateTime,
+    entries: &[FeedEntry],
+) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(
+        "<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\" \
+         xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n<channel>\n",
+    );
+    xml.push_str(&format!(
+        "<title>{}</title>\n<link>{}</link>\n<description>{}</description>\n\
+         <atom:link href=\"{}\" rel=\"self\" type=\"application/rss+xml\"/>\n\
+         <lastBuildDate>{}</lastBuildDate>\n",
+        escape_html(&scope.title()),
+        escape_html(&site_url()),
+        escape_html(&scope.title()),
+        escape_html(self_url),
+        updated.and_utc().to_rfc2822(),
+    ));
+
+    for entry in entries {
+        xml.push_str("<item>\n");
+        xml.push_str(&format!(
+            "<title>{}</title>\n<link>{}</link>\n<guid isPermaLink=\"false\">{}</guid>\n\
+             <pubDate>{}</pubDate>\n",
+            escape_html(&entry.post.title),
+            escape_html(&post_url(&entry.post.slug)),
+            escape_html(&entry.post.id),
+            entry.published().and_utc().to_rfc2822(),
+        ));
+        if let Some(author_name) = &entry.author_name {
+            xml.push_str(&format!(
+                "<dc:creator>{}</dc:creator>\n",
+                escape_html(author_name)
+            ));
+        }
+        for tag in &entry.tags {
+            xml.push_str(&format!("<category>{}</category>\n", escape_html(tag)));
+        }
+        xml.push_str(&format!(
+            "<description>{}</description>\n</item>\n",
+            escape_html(&entry.content())
+        ));
+    }
+
+    xml.push_str("</channel>\n</rss>\n");
+    xml
+}
+
+fn atom_feed(
+    scope: &FeedScope,
+    self_url: &str,
+    updated: NaiveDateTime,
+    entries: &[FeedEntry],
+) -> String {
+    let timestamp =
+        |value: NaiveDateTime| value.and_utc().to_rfc3339_opts(SecondsFormat::Secs, true);
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    xml.push_str(&format!(
+        "<id>{}</id>\n<title>{}</title>\n<updated>{}</updated>\n\
+         <link rel=\"self\" type=\"application/atom+xml\" href=\"{}\"/>\n\
+         <link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>\n",
+        escape_html(self_url),
+        escape_html(&scope.title()),
+        timestamp(updated),
+        escape_html(self_url),
+        escape_html(&site_url()),
+    ));
+
+    for entry in entries {
+        xml.push_str("<entry>\n");
+        xml.push_str(&format!(
+            "<id>urn:uuid:{}</id>\n<title>{}</title>\n\
+             <link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>\n\
+             <published>{}</published>\n<updated>{}</updated>\n\
+             <author><name>{}</name></author>\n",
+            escape_html(&entry.post.id),
+            escape_html(&entry.post.title),
+            escape_html(&post_url(&entry.post.slug)),
+            timestamp(entry.published()),
+            timestamp(entry.updated()),
+            // Atom requires an author; posts of deleted users fall back to the site.
+            escape_html(&entry.author_name.clone().unwrap_or_else(site_title)),
+        ));
+        for tag in &entry.tags {
+            xml.push_str(&format!("<category term=\"{}\"/>\n", escape_html(tag)));
+        }
+        if let Some(excerpt) = &entry.post.excerpt {
+            xml.push_str(&format!("<summary>{}</summary>\n", escape_html(excerpt)));
+        }
+        xml.push_str(&format!(
+            "<content type=\"html\">{}</content>\n</entry>\n",
+            escape_html(&entry.content())
+        ));
+    }
+
+    xml.push_str("</feed>\n");
+    xml
+}
+
+/// Whether the client's cached copy is still current. Only the ETag decides:
+/// `Last-Modified` is the newest entry's update time, which moves backwards
+/// when a post is trashed or unpublished, so `If-Modified-Since` would keep
+/// serving a removed post.
+fn not_modified(req: &HttpRequest, etag: &EntityTag) -> bool {
+    match IfNoneMatch::parse(req) {
+        Ok(IfNoneMatch::Any) => true,
+        Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        Err(_) => false,
+    }
+}
+
+async fn feed_response(
+    data: &AppState,
+    req: &HttpRequest,
+    format: FeedFormat,
+    scope: FeedScope,
+) -> Result<HttpResponse, ApiError> {
+    let (scope, entries) = data
+        .with_conn(move |conn| {
+            let entries = load_entries(conn, &scope)?;
+            Ok((scope, entries))
+        })
+        .await?;
+
+    // An empty feed reports the epoch, so its validators stay stable.
+    let updated = entries
+        .iter()
+        .map(FeedEntry::updated)
+        .max()
+        .unwrap_or_default();
+    let self_url = format!("{}{}", api_url(), req.path());
+
+    let (body, content_type) = match format {
+        FeedFormat::Rss => (
+            rss_feed(&scope, &self_url, updated, &entries),
+            "application/rss+xml; charset=utf-8",
+        ),
+        FeedFormat::Atom => (
+            atom_feed(&scope, &self_url, updated, &entries),
+            "application/atom+xml; charset=utf-8",
+        ),
+    };
+
+    let etag = EntityTag::new_strong(hex::encode(&Sha256::digest(body.as_bytes())[..16]));
+    let seconds = u64::try_from(updated.and_utc().timestamp()).unwrap_or(0);
+    let last_modified = HttpDate::from(UNIX_EPOCH + Duration::from_secs(seconds));
+    let cache_control = CacheControl(vec![
+        CacheDirective::Public,
+        CacheDirective::MaxAge(FEED_MAX_AGE),
+    ]);
+
+    if not_modified(req, &etag) {
+        return Ok(HttpResponse::NotModified()
+            .insert_header(ETag(etag))
+            .insert_header(LastModified(last_modified))
+            .insert_header(cache_control)
+            .finish());
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(ETag(etag))
+        .insert_header(LastModified(last_modified))
+        .insert_header(cache_control)
+        .body(body))
+}
+
+/// The latest published posts as RSS 2.0 (`/feed.rss`) or Atom (`/feed.atom`).
+#[get("/feed.{format:rss|atom}")]
+async fn get_feed(
+    data: Data<AppState>,
+    path: Path<FeedFormat>,
+    req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    feed_response(&data, &req, path.into_inner(), FeedScope::All).await
+}
+
+#[get("/authors/{author_id}/feed.{format:rss|atom}")]
+async fn get_author_feed(
+    data: Data<AppState>,
+    path: Path<(String, FeedFormat)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let (author_id, format) = path.into_inner();
+
+    let name = data
+        .with_conn({
+            let author_id = author_id.clone();
+            move |conn| {
+                users::table
+                    .find(author_id)
+                    .select(users::name)
+                    .first::<String>(conn)
+                    .optional()
+            }
+        })
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Author with id {} not found", author_id)))?;
+
+    feed_response(
+        &data,
+        &req,
+        format,
+        FeedScope::Author {
+            id: author_id,
+            name,
+        },
+    )
+    .await
+}
+
+#[get("/tags/{slug}/feed.{format:rss|atom}")]
+async fn get_tag_feed(
+    data: Data<AppState>,
+    path: Path<(String, FeedFormat)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let (tag_slug, format) = path.into_inner();
+
+    let name = data
+        .with_conn({
+            let tag_slug = tag_slug.clone();
+            move |conn| {
+                tags::table
+                    .filter(tags::slug.eq(tag_slug))
+                    .select(tags::name)
+                    .first::<String>(conn)
+                    .optional()
+            }
+        })
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Tag {} not found", tag_slug)))?;
+
+    feed_response(
+        &data,
+        &req,
+        format,
+        FeedScope::Tag {
+            slug: tag_slug,
+            name,
+        },
+    )
+    .await
+}
@@ -0,0 +1,534 @@
+use actix_multipart::Multipart;
+use actix_web::{
+    delete, get,
+    http::header::{self, CacheControl, CacheDirective},
+    post,
+    web::{self, Data, Path, Query},
+    HttpResponse,
+};
+use diesel::{
+    dsl::exists, Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl,
+    QueryResult, RunQueryDsl, SelectableHelper,
+};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+use crate::{
+    db::{
+        connection::AppState,
+        models::{CreateMedia, Media, MediaVariant},
+        schema::{media, media_variants, posts},
+    },
+    errors::ApiError,
+    middlewares::{auth::AuthenticatedUser, permissions::Permission},
+    storage::Storage,
+    tasks::media_variants::spawn_variant_generation,
+    utils::images::{display_size, strip_metadata},
+};
+
+const MAX_UPLOAD_BYTES: usize = 10 * 1024 * 1024;
+const DEFAULT_PAGE_SIZE: i64 = 20;
+const MAX_PAGE_SIZE: i64 = 100;
+
+/// Accepted uploads and the extension their storage keys get.
+const ALLOWED_TYPES: [(&str, &str); 5] = [
+    ("image/jpeg", "jpg"),
+    ("image/png", "png"),
+    ("image/gif", "gif"),
+    ("image/webp", "webp"),
+    ("application/pdf", "pdf"),
+];
+
+#[derive(Deserialize, Debug)]
+pub struct MediaListQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct MediaVariantResponse {
+    #[serde(flatten)]
+    pub variant: MediaVariant,
+    pub url: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct MediaResponse {
+    #[serde(flatten)]
+    pub media: Media,
+    pub url: String,
+    /// Resized renditions of images. Empty until background generation has
+    /// finished, and always empty for other files.
+    pub variants: Vec<MediaVariantResponse>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct MediaPage {
+    pub media: Vec<MediaResponse>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+pub fn media_response(
+    storage: &dyn Storage,
+    media: Media,
+    variants: Vec<MediaVariant>,
+) -> MediaResponse {
+    MediaResponse {
+        url: storage.url(&media.storage_key),
+        media,
+        variants: variants
+            .into_iter()
+            .map(|variant| MediaVariantResponse {
+                url: storage.url(&variant.storage_key),
+                variant,
+            })
+            .collect(),
+    }
+}
+
+/// Variants of the given media, grouped by media id and ordered by size.
+fn load_variants(
+    conn: &mut PgConnection,
+    media_ids: &[String],
+) -> QueryResult<HashMap<String, Vec<MediaVariant>>> {
+    let variants = media_variants::table
+        .filter(media_variants::media_id.eq_any(media_ids))
+        .order((
+            media_variants::width.asc(),
+            media_variants::content_type.asc(),
+        ))
+        .select(MediaVariant::as_select())
+        .load::<MediaVariant>(conn)?;
+
+    let mut grouped: HashMap<String, Vec<MediaVariant>> = HashMap::new();
+    for variant in variants {
+        grouped
+            .entry(variant.media_id.clone())
+            .or_default()
+            .push(variant);
+    }
+    Ok(grouped)
+}
+
+async fn media_with_variants(data: &AppState, item: Media) -> Result<MediaResponse, ApiError> {
+    data.blocking(move |data| {
+        let mut conn = data.conn()?;
+        let variants = load_variants(&mut conn, std::slice::from_ref(&item.id))?
+            .remove(&item.id)
+            .unwrap_or_default();
+        Ok(media_response(data.storage.as_ref(), item, variants))
+    })
+    .await
+}
+
+async fn find_media(data: &AppState, media_id: &str) -> Result<Option<Media>, ApiError> {
+    let media_id = media_id.to_string();
+    data.with_conn(move |conn| {
+        media::table
+            .find(media_id)
+            .select(Media::as_select())
+            .first::<Media>(conn)
+            .optional()
+    })
+    .await
+}
+
+/// The file's real type, read from its leading bytes rather than trusted from
+/// the client.
+fn sniff_content_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && bytes.starts_with(b"RIFF") && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else if bytes.starts_with(b"%PDF-") {
+        Some("application/pdf")
+    } else {
+        None
+    }
+}
+
+/// Keeps the last path component and drops control characters, so the name is
+/// safe to echo back in headers and listings.
+fn clean_file_name(name: &str) -> String {
+    let name: String = name
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(255)
+        .collect();
+
+    if name.trim().is_empty() {
+        "upload".into()
+    } else {
+        name
+    }
+}
+
+fn media_not_found(media_id: &str) -> ApiError {
+    ApiError::NotFound(format!("Media with id {} not found", media_id))
+}
+
+fn storage_error(error: Box<dyn std::error::Error + Send + Sync>) -> ApiError {
+    ApiError::Internal(format!("Storage error: {}", error))
+}
+
+fn invalid_multipart(error: actix_multipart::MultipartError) -> ApiError {
+    ApiError::BadRequest(format!("Invalid multipart body: {}", error))
+}
+
+/// Uploads one file from the multipart `file` field. Uploading a file the user
+/// already has returns the existing media with a 200 instead of a 201.
+#[post("/media")]
+async fn upload_media(
+    data: Data<AppState>,
+    mut payload: Multipart,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    use crate::db::schema::media::dsl::{checksum, media, storage_key, user_id};
+
+    user.authorize(Permission::UploadMedia, "upload media")?;
+
+    let mut upload = None;
+    while let Some(field) = payload.next().await {
+        let mut field = field.map_err(invalid_multipart)?;
+        if field.name() != Some("file") {
+            continue;
+        }
+
+        let file_name = clean_file_name(
+            field
+                .content_disposition()
+                .and_then(|disposition| disposition.get_filename())
+                .unwrap_or_default(),
+        );
+        let declared_type = field
+            .content_type()
+            .map(|mime| mime.essence_str().to_string());
+
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk.map_err(invalid_multipart)?;
+            if bytes.len() + chunk.len() > MAX_UPLOAD_BYTES {
+                return Err(ApiError::PayloadTooLarge(format!(
+                    "Files can be at most {} bytes",
+                    MAX_UPLOAD_BYTES
+                )));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        upload = Some((file_name, declared_type, bytes));
+        break;
+    }
+
+    let (file_name, declared_type, bytes) =
+        upload.ok_or_else(|| ApiError::BadRequest("Missing file field".into()))?;
+    if bytes.is_empty() {
+        return Err(ApiError::BadRequest("The uploaded file is empty".into()));
+    }
+
+    let (content_type, extension) = sniff_content_type(&bytes)
+        .and_then(|sniffed| {
+            ALLOWED_TYPES
+                .into_iter()
+                .find(|(allowed, _)| *allowed == sniffed)
+        })
+        .ok_or_else(|| {
+            ApiError::UnsupportedMediaType(
+                "Only JPEG, PNG, GIF, WebP and PDF files can be uploaded".into(),
+            )
+        })?;
+    if declared_type.is_some_and(|declared| declared != content_type) {
+        return Err(ApiError::UnsupportedMediaType(
+            "The file's contents don't match its declared type".into(),
+        ));
+    }
+
+    let unreadable = || ApiError::BadRequest("The image could not be read".into());
+
+    // Cleaned before hashing, so the same photo dedupes whatever metadata it
+    // was uploaded with.
+    let bytes = strip_metadata(&bytes, content_type).ok_or_else(unreadable)?;
+
+    let dimensions = if content_type.starts_with("image/") {
+        let (width, height) = display_size(&bytes).ok_or_else(unreadable)?;
+        Some((width as i32, height as i32))
+    } else {
+        None
+    };
+
+    let file_checksum = hex::encode(Sha256::digest(&bytes));
+    let key = format!("{}.{}", file_checksum, extension);
+
+    let duplicate = data
+        .with_conn({
+            let owner_id = user.user_id.clone();
+            let file_checksum = file_checksum.clone();
+            move |conn| {
+                media
+                    .filter(user_id.eq(owner_id))
+                    .filter(checksum.eq(file_checksum))
+                    .select(Media::as_select())
+                    .first::<Media>(conn)
+                    .optional()
+            }
+        })
+        .await?;
+
+    if let Some(existing) = duplicate {
+        return Ok(HttpResponse::Ok().json(media_with_variants(&data, existing).await?));
+    }
+
+    // Keys come from the checksum, so another user's upload of the same file
+    // already put the bytes in storage.
+    let already_stored = data
+        .with_conn({
+            let key = key.clone();
+            move |conn| {
+                diesel::select(exists(media.filter(storage_key.eq(key)))).get_result::<bool>(conn)
+            }
+        })
+        .await?;
+
+    let size_bytes = bytes.len() as i64;
+    if !already_stored {
+        let storage = data.storage.clone();
+        let stored_key = key.clone();
+        web::block(move || storage.put(&stored_key, &bytes, content_type))
+            .await?
+            .map_err(storage_error)?;
+    }
+
+    let new_media = CreateMedia::new(
+        user.user_id.clone(),
+        key,
+        file_name,
+        content_type.to_string(),
+        size_bytes,
+        dimensions,
+        file_checksum,
+    );
+
+    let created = data
+        .with_conn(move |conn| {
+            diesel::insert_into(media)
+                .values(&new_media)
+                .returning(Media::as_returning())
+                .get_result::<Media>(conn)
+        })
+        .await
+        .map_err(|e| match e {
+            ApiError::Conflict(_) => {
+                ApiError::Conflict("This file is already being uploaded".into())
+            }
+            e => e,
+        })?;
+
+    if created.is_image() {
+        spawn_variant_generation(
+            data.pool()?.clone(),
+            data.storage.clone(),
+            data.image_variants.clone(),
+            created.clone(),
+        );
+    }
+    Ok(HttpResponse::Created().json(media_response(data.storage.as_ref(), created, Vec::new())))
+}
+
+/// The signed-in user's uploads, newest first.
+#[get("/media")]
+async fn get_media_list(
+    data: Data<AppState>,
+    query: Query<MediaListQuery>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    use crate::db::schema::media::dsl::{created_at, id, media, user_id};
+
+    let list_query = query.into_inner();
+    let limit = list_query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let offset = list_query.offset.unwrap_or(0).max(0);
+
+    let (total, page, mut variants) = data
+        .with_conn(move |conn| {
+            let total = media
+                .filter(user_id.eq(&user.user_id))
+                .count()
+                .get_result::<i64>(conn)?;
+            let page = media
+                .filter(user_id.eq(&user.user_id))
+                .order((created_at.desc(), id.desc()))
+                .limit(limit)
+                .offset(offset)
+                .select(Media::as_select())
+                .load::<Media>(conn)?;
+            let ids: Vec<String> = page.iter().map(|item| item.id.clone()).collect();
+            let variants = load_variants(conn, &ids)?;
+            Ok((total, page, variants))
+        })
+        .await?;
+
+    Ok(HttpResponse::Ok().json(MediaPage {
+        media: page
+            .into_iter()
+            .map(|item| {
+                let item_variants = variants.remove(&item.id).unwrap_or_default();
+                media_response(data.storage.as_ref(), item, item_variants)
+            })
+            .collect(),
+        total,
+        limit,
+        offset,
+    }))
+}
+
+/// One upload with its variants, for its uploader or those who manage all
+/// media; the metadata names the uploader and the original file name.
+#[get("/media/{media_id}")]
+async fn get_media(
+    data: Data<AppState>,
+    path: Path<String>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let media_id = path.into_inner();
+
+    let item = find_media(&data, &media_id)
+        .await?
+        .ok_or_else(|| media_not_found(&media_id))?;
+
+    user.authorize_owned(
+        item.user_id.as_deref(),
+        Permission::UploadMedia,
+        Permission::ManageAnyMedia,
+        "view this media",
+    )?;
+
+    Ok(HttpResponse::Ok().json(media_with_variants(&data, item).await?))
+}
+
+/// Serves stored originals and variants for backends without their own public
+/// URL, such as local storage. Keys are content hashes, so responses never go
+/// stale.
+#[get("/media/files/{key}")]
+async fn get_media_file(
+    data: Data<AppState>,
+    path: Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let key = path.into_inner();
+    let file_not_found = || ApiError::NotFound("File not found".into());
+
+    let content_type = data
+        .with_conn({
+            let key = key.clone();
+            move |conn| {
+                media::table
+                    .filter(media::storage_key.eq(&key))
+                    .select(media::content_type)
+                    .first::<String>(conn)
+                    .optional()
+                    .and_then(|content_type| match content_type {
+                        Some(content_type) => Ok(Some(content_type)),
+                        None => media_variants::table
+                            .filter(media_variants::storage_key.eq(&key))
+                            .select(media_variants::content_type)
+                            .first::<String>(conn)
+                            .optional(),
+                    })
+            }
+        })
+        .await?
+        .ok_or_else(file_not_found)?;
+
+    let storage = data.storage.clone();
+    let bytes = web::block(move || storage.get(&key))
+        .await?
+        .map_err(storage_error)?
+        .ok_or_else(file_not_found)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(31_536_000),
+            CacheDirective::Extension("immutable".into(), None),
+        ]))
+        .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .body(bytes))
+}
+
+/// Deletes the media and detaches it from any post using it as a cover. The
+/// stored file and its variants go once no other media row shares them.
+#[delete("/media/{media_id}")]
+async fn delete_media(
+    data: Data<AppState>,
+    path: Path<String>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let media_id = path.into_inner();
+
+    let item = find_media(&data, &media_id)
+        .await?
+        .ok_or_else(|| media_not_found(&media_id))?;
+
+    user.authorize_owned(
+        item.user_id.as_deref(),
+        Permission::UploadMedia,
+        Permission::ManageAnyMedia,
+        "delete this media",
+    )?;
+
+    let (still_shared, variant_keys) = data
+        .with_conn({
+            let item = item.clone();
+            move |conn| {
+                conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                    diesel::update(posts::table.filter(posts::cover_media_id.eq(&item.id)))
+                        .set((
+                            posts::cover_media_id.eq(None::<String>),
+                            posts::cover_image_url.eq(None::<String>),
+                            posts::version.eq(posts::version + 1),
+                        ))
+                        .execute(conn)?;
+                    let variant_keys = media_variants::table
+                        .filter(media_variants::media_id.eq(&item.id))
+                        .select(media_variants::storage_key)
+                        .load::<String>(conn)?;
+                    diesel::delete(media::table.find(&item.id)).execute(conn)?;
+
+                    let still_shared = diesel::select(exists(
+                        media::table.filter(media::storage_key.eq(&item.storage_key)),
+                    ))
+                    .get_result::<bool>(conn)?;
+                    Ok((still_shared, variant_keys))
+                })
+            }
+        })
+        .await?;
+
+    if !still_shared {
+        let storage = data.storage.clone();
+        let mut keys = variant_keys;
+        keys.push(item.storage_key.clone());
+        // The row is gone either way; files left behind only cost space.
+        let removed = web::block(move || keys.iter().try_for_each(|key| storage.delete(key))).await;
+        if !matches!(removed, Ok(Ok(()))) {
+            eprintln!("Failed to delete the stored files of media {}", item.id);
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": format!("Media successfully deleted with id {}", media_id)
+    })))
+}
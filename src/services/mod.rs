@@ -1,2 +1,9 @@
+pub mod comments;
+pub mod feeds;
+pub mod media;
 pub mod posts;
+pub mod revisions;
+pub mod search;
+pub mod sitemap;
+pub mod taxonomy;
 pub mod users;
@@ -1,19 +1,29 @@
 use actix_web::{
-    delete, get, post, put,
-    web::{Data, Json, Path},
-    HttpMessage, HttpRequest, HttpResponse, Responder,
+    delete, get,
+    http::header::{self, ETag, EntityTag, Header, IfMatch, IfNoneMatch},
+    patch, post, put,
+    web::{Data, Json, Path, Query},
+    HttpRequest, HttpResponse,
 };
-use diesel::{
-    query_dsl::methods::{FilterDsl, FindDsl, SelectDsl},
-    result::{DatabaseErrorKind, Error::DatabaseError},
-    ExpressionMethods, OptionalExtension, RunQueryDsl, SelectableHelper,
-};
-use serde::Deserialize;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::{OptionalExtension, QueryDsl, RunQueryDsl, SelectableHelper};
+use serde::{Deserialize, Deserializer, Serialize};
 use validator::Validate;
 
-use crate::db::{
-    connection::AppState,
-    models::{CreatePost, Post},
+use crate::{
+    db::{
+        connection::AppState,
+        models::{BodyFormat, CreatePost, Media, Post, PostStatus, Term, UpdatePost},
+        schema::media,
+    },
+    errors::ApiError,
+    middlewares::{
+        auth::{AuthenticatedUser, OptionalUser},
+        permissions::Permission,
+    },
+    repositories::{KeysetPage, PostEdit, PostFilter, PostRepository},
+    services::taxonomy::normalize_terms,
+    utils::{render::render_body, slug::slugify},
 };
 
 #[derive(Deserialize, Validate, Debug)]
@@ -30,155 +40,517 @@ pub struct CreatePostRequest {
         message = "Body length must be between 6 and 200000 characters"
     ))]
     pub body: String,
+    /// Defaults to markdown for new posts and to the current format on update.
+    pub body_format: Option<BodyFormat>,
+    /// Defaults to one generated from the title.
+    #[validate(length(
+        min = 1,
+        max = 80,
+        message = "Slug length must be between 1 and 80 characters"
+    ))]
+    pub slug: Option<String>,
+    /// Tag names, created on first use. Left unchanged on update when absent.
+    #[validate(length(max = 20, message = "A post can have at most 20 tags"))]
+    pub tags: Option<Vec<String>>,
+    /// Category names, created on first use. Left unchanged on update when
+    /// absent.
+    #[validate(length(max = 5, message = "A post can have at most 5 categories"))]
+    pub categories: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Validate, Debug)]
+pub struct PatchPostRequest {
+    #[validate(length(
+        min = 10,
+        max = 80,
+        message = "Title length must be between 10 and 80 characters"
+    ))]
+    pub title: Option<String>,
+    #[validate(length(
+        min = 6,
+        max = 200000,
+        message = "Body length must be between 6 and 200000 characters"
+    ))]
+    pub body: Option<String>,
+    pub body_format: Option<BodyFormat>,
+    #[validate(length(
+        min = 1,
+        max = 80,
+        message = "Slug length must be between 1 and 80 characters"
+    ))]
+    pub slug: Option<String>,
+    /// `true` publishes the post now, `false` returns it to draft.
+    pub published: Option<bool>,
+    #[validate(length(max = 20, message = "A post can have at most 20 tags"))]
+    pub tags: Option<Vec<String>>,
+    #[validate(length(max = 5, message = "A post can have at most 5 categories"))]
+    pub categories: Option<Vec<String>>,
+    /// `null` clears it.
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    #[validate(length(
+        min = 1,
+        max = 300,
+        message = "Excerpt length must be between 1 and 300 characters"
+    ))]
+    pub excerpt: Option<Option<String>>,
+    /// `null` clears it.
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    #[validate(url(message = "Cover image must be a valid URL"))]
+    pub cover_image_url: Option<Option<String>>,
+}
+
+/// Tells a field sent as `null` (`Some(None)`) apart from one left out
+/// (`None`, via `#[serde(default)]`).
+fn deserialize_nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Normalized tags and categories from a create or update request.
+type RequestedTerms = (Option<Vec<Term>>, Option<Vec<Term>>);
+
+fn requested_terms(
+    tags: &Option<Vec<String>>,
+    categories: &Option<Vec<String>>,
+) -> Result<RequestedTerms, String> {
+    let normalize = |names: &Option<Vec<String>>| names.as_deref().map(normalize_terms).transpose();
+
+    Ok((normalize(tags)?, normalize(categories)?))
+}
+
+/// A single post with its tags and categories.
+#[derive(Serialize, Debug)]
+pub struct PostDetail {
+    #[serde(flatten)]
+    pub post: Post,
+    pub tags: Vec<Term>,
+    pub categories: Vec<Term>,
+}
+
+/// Loads the post's terms. Posts the render backfill hasn't reached yet are
+/// rendered for this response only; reads never write.
+async fn load_detail(data: &AppState, mut post: Post) -> Result<PostDetail, ApiError> {
+    data.blocking(move |data| {
+        if post.rendered_body.is_none() {
+            post.rendered_body = Some(render_body(&post.body, post.body_format));
+        }
+        let post_ids = [post.id.as_str()];
+        let tags = data.posts.tags(&post_ids)?.remove(&post.id);
+        let categories = data.posts.categories(&post_ids)?.remove(&post.id);
+
+        Ok(PostDetail {
+            post,
+            tags: tags.unwrap_or_default(),
+            categories: categories.unwrap_or_default(),
+        })
+    })
+    .await
+}
+
+pub fn post_not_found(post_id: &str) -> ApiError {
+    ApiError::NotFound(format!("Post with id {} not found", post_id))
+}
+
+/// Loads a post that isn't in the trash.
+async fn find_post(data: &AppState, post_id: &str) -> Result<Post, ApiError> {
+    let post_id = post_id.to_string();
+    data.blocking(move |data| {
+        data.posts
+            .find(&post_id)?
+            .ok_or_else(|| post_not_found(&post_id))
+    })
+    .await
+}
+
+/// Picks the slug for a new or updated post. An explicit slug must be free;
+/// a generated one gets `-2`, `-3`, ... appended until it is. Updates that
+/// keep the title and give no slug keep the current one.
+fn choose_slug(
+    posts: &dyn PostRepository,
+    requested: Option<&str>,
+    new_title: &str,
+    current: Option<&Post>,
+) -> Result<String, ApiError> {
+    let post_id = current.map(|post| post.id.as_str());
+
+    if let Some(requested) = requested {
+        let candidate = slugify(requested);
+        if candidate.is_empty() {
+            return Err(ApiError::BadRequest(
+                "Slug must contain at least one letter or digit".into(),
+            ));
+        }
+        if posts.slug_taken(&candidate, post_id)? {
+            return Err(ApiError::Conflict("Slug already exists".into()));
+        }
+        return Ok(candidate);
+    }
+
+    if let Some(post) = current.filter(|post| post.title == new_title) {
+        return Ok(post.slug.clone());
+    }
+
+    let mut base = slugify(new_title);
+    if base.is_empty() {
+        base = "post".into();
+    }
+
+    let mut candidate = base.clone();
+    let mut suffix = 2;
+    while posts.slug_taken(&candidate, post_id)? {
+        candidate = format!("{}-{}", base, suffix);
+        suffix += 1;
+    }
+
+    Ok(candidate)
 }
 
 #[post("/posts/create")]
 async fn create_post(
     data: Data<AppState>,
     body: Json<CreatePostRequest>,
-    req: HttpRequest,
-) -> impl Responder {
-    use crate::db::schema::posts::dsl::{posts, title};
-
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
     let create_post_data = body.into_inner();
+    create_post_data.validate()?;
 
-    if let Err(validation_errors) = create_post_data.validate() {
-        let create_post_error_messages: Vec<String> = validation_errors
-            .field_errors()
-            .into_iter()
-            .map(|(field, errors)| {
-                format!(
-                    "Invalid {}: {}",
-                    field,
-                    errors
-                        .into_iter()
-                        .map(|e| e.message.clone().unwrap_or_else(|| "Invalid value".into()))
-                        .collect::<Vec<_>>()
-                        .join(", ")
-                )
-            })
-            .collect();
+    let (new_tags, new_categories) =
+        requested_terms(&create_post_data.tags, &create_post_data.categories)
+            .map_err(ApiError::BadRequest)?;
 
-        return HttpResponse::BadRequest().json(serde_json::json!({
-            "error": create_post_error_messages,
-        }));
-    }
+    user.authorize(Permission::CreatePost, "create posts")?;
 
-    if let Some(authenticated_user_id) = req.extensions().get::<String>() {
-        match data.pool.get() {
-            Ok(mut conn) => {
-                let posts_exists = posts
-                    .filter(title.eq(&create_post_data.title))
-                    .first::<Post>(&mut conn);
-
-                match posts_exists {
-                    Ok(_) => {
-                        return HttpResponse::Conflict().json(serde_json::json!({
-                            "error": "Title already exists",
-                    
-                        }));
-                    }
-                    Err(_) => {
-                        let new_post = CreatePost::new(
-                            create_post_data.title,
-                            create_post_data.body,
-                            authenticated_user_id.to_string(),
-                        );
-
-                        match diesel::insert_into(posts)
-                            .values(new_post)
-                            .returning(Post::as_returning())
-                            .get_result::<Post>(&mut conn)
-                        {
-                            Ok(post) => {
-                                return HttpResponse::Created().json(serde_json::json!({
-                                    "success": format!("Post successfully created with id {}", post.id),
-                                   
-                                }));
-                            }
-                            Err(DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
-                                return HttpResponse::Conflict().json(serde_json::json!({
-                                    "error": "Title already exists",
-                               
-                                }));
-                            }
-                            Err(e) => {
-                                return HttpResponse::InternalServerError().json(
-                                    serde_json::json!({
-                                        "error": format!("An error occurred while deleting the post. Error:- {}", e),
-                                        
-                                    }),
-                                );
-                            }
-                        }
-                    }
-                }
+    let post = data
+        .blocking(move |data| {
+            if data.posts.title_exists(&create_post_data.title)? {
+                return Err(ApiError::Conflict("Title already exists".into()));
             }
-            Err(_) => {
-                return HttpResponse::InternalServerError().json(serde_json::json!({
-                    "error": "An error occurred while connecting to the database",
-             
-                }));
-            }
-        }
-    } else {
-        return HttpResponse::Unauthorized().json(serde_json::json!({
-            "error": "Missing or invalid token",
-     
-        }));
-    }
+
+            let new_slug = choose_slug(
+                data.posts.as_ref(),
+                create_post_data.slug.as_deref(),
+                &create_post_data.title,
+                None,
+            )?;
+
+            let new_post = CreatePost::new(
+                create_post_data.title,
+                create_post_data.body,
+                create_post_data.body_format.unwrap_or(BodyFormat::Markdown),
+                user.user_id,
+                new_slug,
+            );
+
+            Ok(data
+                .posts
+                .create(new_post, new_tags.as_deref(), new_categories.as_deref())?)
+        })
+        .await?;
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "success": format!("Post successfully created with id {}", post.id),
+        "slug": post.slug,
+    })))
+}
+
+const DEFAULT_PAGE_SIZE: i64 = 20;
+const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PostListQuery {
+    pub limit: Option<i64>,
+    /// Cursor from a previous page's `next_cursor`.
+    pub after: Option<String>,
+    /// Cursor from a previous page's `prev_cursor`.
+    pub before: Option<String>,
+    pub author: Option<String>,
+    pub status: Option<PostStatus>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    /// Tag slug.
+    pub tag: Option<String>,
+    /// Category slug.
+    pub category: Option<String>,
+    #[serde(default)]
+    pub order: SortOrder,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PostAuthor {
+    pub id: String,
+    pub name: String,
+}
+
+/// List projection of a post: everything but the full body.
+#[derive(Serialize, Debug)]
+pub struct PostSummary {
+    pub id: String,
+    pub slug: String,
+    pub title: String,
+    /// The post's own excerpt, or the start of its body when it has none.
+    pub excerpt: String,
+    pub cover_image_url: Option<String>,
+    pub author: Option<PostAuthor>,
+    pub status: PostStatus,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+    pub published_at: Option<NaiveDateTime>,
+    pub tags: Vec<Term>,
+    pub categories: Vec<Term>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PostPage {
+    pub posts: Vec<PostSummary>,
+    pub total: i64,
+    pub limit: i64,
+    pub next_cursor: Option<String>,
+    pub prev_cursor: Option<String>,
+}
+
+/// Cursors are the keyset `(created_at, id)` of a row, hex-encoded so clients
+/// treat them as opaque.
+fn encode_cursor(created_at: NaiveDateTime, id: &str) -> String {
+    hex::encode(format!(
+        "{}|{}",
+        created_at.and_utc().timestamp_micros(),
+        id
+    ))
+}
+
+fn decode_cursor(cursor: &str) -> Option<(NaiveDateTime, String)> {
+    let decoded = String::from_utf8(hex::decode(cursor).ok()?).ok()?;
+    let (micros, id) = decoded.split_once('|')?;
+    let created_at = DateTime::from_timestamp_micros(micros.parse().ok()?)?.naive_utc();
+
+    Some((created_at, id.to_string()))
 }
 
 #[get("/posts")]
-async fn get_posts(data: Data<AppState>) -> impl Responder {
-    use crate::db::schema::posts::dsl::posts;
-
-    match data.pool.get() {
-        Ok(mut conn) => {
-            let all_posts = posts.select(Post::as_select()).load::<Post>(&mut conn);
-
-            match all_posts {
-                Ok(all) => HttpResponse::Ok().json(all),
-                Err(_) => HttpResponse::NotFound().json(serde_json::json!({
-                    "error": "No posts found in the database",
-           
-                })),
-            }
-        }
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "An error occurred while connecting to the database",
-        })),
+async fn get_posts(
+    data: Data<AppState>,
+    query: Query<PostListQuery>,
+    OptionalUser(user): OptionalUser,
+) -> Result<HttpResponse, ApiError> {
+    list_posts(&data, query.into_inner(), user).await
+}
+
+/// The keyset-paginated post listing behind `GET /posts` and the per-tag and
+/// per-category listings.
+pub async fn list_posts(
+    data: &AppState,
+    list_query: PostListQuery,
+    user: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, ApiError> {
+    let limit = list_query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let status = list_query.status.unwrap_or(PostStatus::Published);
+
+    // Only published posts are public; other states need edit rights over
+    // the posts being listed.
+    if status != PostStatus::Published {
+        let Some(user) = &user else {
+            return Err(ApiError::Unauthorized("Missing or invalid token".into()));
+        };
+        user.authorize_owned(
+            list_query.author.as_deref(),
+            Permission::EditOwnPost,
+            Permission::EditAnyPost,
+            "list unpublished posts",
+        )?;
     }
+
+    let (cursor, paging_backwards) = match (&list_query.after, &list_query.before) {
+        (Some(_), Some(_)) => {
+            return Err(ApiError::BadRequest(
+                "Only one of after and before may be given".into(),
+            ));
+        }
+        (Some(cursor), None) => (Some(cursor), false),
+        (None, Some(cursor)) => (Some(cursor), true),
+        (None, None) => (None, false),
+    };
+    let cursor = cursor
+        .map(|cursor| {
+            decode_cursor(cursor).ok_or_else(|| ApiError::BadRequest("Invalid cursor".into()))
+        })
+        .transpose()?;
+
+    let filter = PostFilter {
+        status,
+        author: list_query.author,
+        from: list_query.from.map(|from| from.naive_utc()),
+        to: list_query.to.map(|to| to.naive_utc()),
+        tag: list_query.tag,
+        category: list_query.category,
+    };
+    let came_forward = list_query.after.is_some();
+    // Paging backwards walks the keyset in the opposite direction and flips
+    // the rows afterwards.
+    let keyset_page = KeysetPage {
+        after: cursor,
+        ascending: (list_query.order == SortOrder::Asc) != paging_backwards,
+        limit: limit + 1,
+    };
+
+    let page = data
+        .blocking(move |data| {
+            let total = data.posts.count(&filter)?;
+
+            let mut rows = data.posts.list(&filter, &keyset_page)?;
+            let has_more = rows.len() as i64 > limit;
+            rows.truncate(limit as usize);
+            if paging_backwards {
+                rows.reverse();
+            }
+
+            let author_ids: Vec<&str> = rows
+                .iter()
+                .filter_map(|row| row.user_id.as_deref())
+                .collect();
+            let authors = data.users.names(&author_ids)?;
+
+            let post_ids: Vec<&str> = rows.iter().map(|row| row.id.as_str()).collect();
+            let mut post_tags = data.posts.tags(&post_ids)?;
+            let mut post_categories = data.posts.categories(&post_ids)?;
+
+            let first_cursor = rows
+                .first()
+                .map(|row| encode_cursor(row.created_at, &row.id));
+            let last_cursor = rows
+                .last()
+                .map(|row| encode_cursor(row.created_at, &row.id));
+            let (next_cursor, prev_cursor) = if paging_backwards {
+                (last_cursor, first_cursor.filter(|_| has_more))
+            } else {
+                (
+                    last_cursor.filter(|_| has_more),
+                    first_cursor.filter(|_| came_forward),
+                )
+            };
+
+            let summaries = rows
+                .into_iter()
+                .map(|row| PostSummary {
+                    author: row.user_id.as_ref().and_then(|author_id| {
+                        authors.get(author_id).map(|name| PostAuthor {
+                            id: author_id.to_string(),
+                            name: name.to_string(),
+                        })
+                    }),
+                    tags: post_tags.remove(&row.id).unwrap_or_default(),
+                    categories: post_categories.remove(&row.id).unwrap_or_default(),
+                    id: row.id,
+                    slug: row.slug,
+                    title: row.title,
+                    excerpt: row.excerpt,
+                    cover_image_url: row.cover_image_url,
+                    status: row.status,
+                    created_at: row.created_at,
+                    updated_at: row.updated_at,
+                    published_at: row.published_at,
+                })
+                .collect();
+
+            Ok(PostPage {
+                posts: summaries,
+                total,
+                limit,
+                next_cursor,
+                prev_cursor,
+            })
+        })
+        .await?;
+
+    Ok(HttpResponse::Ok().json(page))
 }
 
 #[get("/posts/{post_id}")]
-async fn get_post(data: Data<AppState>, path: Path<String>) -> impl Responder {
-    use crate::db::schema::posts::dsl::posts;
-
+async fn get_post(
+    data: Data<AppState>,
+    path: Path<String>,
+    req: HttpRequest,
+    OptionalUser(user): OptionalUser,
+) -> Result<HttpResponse, ApiError> {
     let post_id = path.into_inner();
 
-    match data.pool.get() {
-        Ok(mut conn) => {
-            let get_post = posts
-                .find(&post_id)
-                .select(Post::as_select())
-                .first::<Post>(&mut conn)
-                .optional();
-
-            match get_post {
-                Ok(Some(post)) => HttpResponse::Ok().json(post),
-                Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
-                    "error": format!("Post with id {} not found", post_id)
-                })),
-                Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-                    "error": "An error occurred while retrieving the post"
-                })),
+    let post = Some(find_post(&data, &post_id).await?)
+        .filter(|post| can_view_post(post, user.as_ref()))
+        .ok_or_else(|| post_not_found(&post_id))?;
+
+    let etag = post_etag(&post);
+    if not_modified(&req, &etag) {
+        return Ok(HttpResponse::NotModified()
+            .insert_header(ETag(etag))
+            .finish());
+    }
+
+    let detail = load_detail(&data, post).await?;
+    Ok(HttpResponse::Ok().insert_header(ETag(etag)).json(detail))
+}
+
+/// Looks a post up by its current slug. Former slugs answer with a 301 to the
+/// post's current slug.
+#[get("/posts/by-slug/{slug}")]
+async fn get_post_by_slug(
+    data: Data<AppState>,
+    path: Path<String>,
+    req: HttpRequest,
+    OptionalUser(user): OptionalUser,
+) -> Result<HttpResponse, ApiError> {
+    let requested_slug = path.into_inner();
+
+    let post = data
+        .blocking({
+            let requested_slug = requested_slug.clone();
+            move |data| {
+                Ok(match data.posts.find_by_slug(&requested_slug)? {
+                    Some(post) => Some((post, false)),
+                    None => data
+                        .posts
+                        .find_by_former_slug(&requested_slug)?
+                        .map(|post| (post, true)),
+                })
             }
-        }
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "An error occurred while connecting to the database"
-        })),
+        })
+        .await?;
+
+    let Some((post, former_slug)) = post.filter(|(post, _)| can_view_post(post, user.as_ref()))
+    else {
+        return Err(ApiError::NotFound(format!(
+            "Post with slug {} not found",
+            requested_slug
+        )));
+    };
+
+    if former_slug {
+        return Ok(HttpResponse::MovedPermanently()
+            .insert_header((header::LOCATION, format!("/posts/by-slug/{}", post.slug)))
+            .finish());
     }
+
+    let etag = post_etag(&post);
+    if not_modified(&req, &etag) {
+        return Ok(HttpResponse::NotModified()
+            .insert_header(ETag(etag))
+            .finish());
+    }
+
+    let detail = load_detail(&data, post).await?;
+    Ok(HttpResponse::Ok().insert_header(ETag(etag)).json(detail))
 }
 
 #[put("/posts/{post_id}/update")]
@@ -187,141 +559,881 @@ async fn update_post(
     path: Path<String>,
     update_body: Json<CreatePostRequest>,
     req: HttpRequest,
-) -> impl Responder {
-    use crate::db::schema::posts::dsl::{body, id, posts, title};
-
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
     let post_id = path.into_inner();
     let update_post_data = update_body.into_inner();
+    update_post_data.validate()?;
 
-    if let Err(validation_errors) = update_post_data.validate() {
-        let update_post_error_messages: Vec<String> = validation_errors
-            .field_errors()
-            .into_iter()
-            .map(|(field, errors)| {
-                format!(
-                    "Invalid {}: {}",
-                    field,
-                    errors
-                        .into_iter()
-                        .map(|e| e.message.clone().unwrap_or_else(|| "Invalid value".into()))
-                        .collect::<Vec<_>>()
-                        .join(", ")
-                )
-            })
-            .collect();
+    let (new_tags, new_categories) =
+        requested_terms(&update_post_data.tags, &update_post_data.categories)
+            .map_err(ApiError::BadRequest)?;
 
-        return HttpResponse::BadRequest().json(serde_json::json!({
-            "error": update_post_error_messages
-        }));
+    let post = find_post(&data, &post_id).await?;
+
+    user.authorize_owned(
+        post.user_id.as_deref(),
+        Permission::EditOwnPost,
+        Permission::EditAnyPost,
+        "update this post",
+    )?;
+
+    if !if_match_passes(&req, &post) {
+        return Err(precondition_failed());
     }
 
-    if let Some(authenticated_user_id) = req.extensions().get::<String>() {
-        match data.pool.get() {
-            Ok(mut conn) => {
-                let post_exists = posts.filter(id.eq(&post_id)).first::<Post>(&mut conn);
-
-                match post_exists {
-                    Ok(post) => {
-                        if post.user_id == Some(authenticated_user_id.to_string()) {
-                            let updated_post = diesel::update(posts.find(post_id))
-                                .set((
-                                    title.eq(update_post_data.title),
-                                    body.eq(update_post_data.body),
-                                ))
-                                .returning(Post::as_returning())
-                                .get_result::<Post>(&mut conn);
-
-                            match updated_post {
-                                Ok(post) => HttpResponse::Ok().json(serde_json::json!({
-                                    "success": format!("Post successfully updated with id {}", post.id),
-                                    
-                                })),
-                                Err(DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
-                                    HttpResponse::Conflict().json(serde_json::json!({
-                                        "error": "Title already exists",
-                                   
-                                    }))
-                                }
-                                Err(e) => {
-                                    HttpResponse::InternalServerError().json(serde_json::json!({
-                                        "error": format!("An error occurred while updating the post. Error:- {}", e),
-                                        
-                                    }))
-                                }
-                            }
-                        } else {
-                            HttpResponse::Unauthorized().json(serde_json::json!({
-                                "error": "You do not have permission to update this post",
-                             
-                            }))
-                        }
-                    }
-                    Err(_) => HttpResponse::NotFound().json(serde_json::json!({
-                        "error": format!("Post with id {} not found", post_id),
+    let post = data
+        .blocking(move |data| {
+            let new_slug = choose_slug(
+                data.posts.as_ref(),
+                update_post_data.slug.as_deref(),
+                &update_post_data.title,
+                Some(&post),
+            )?;
+
+            let new_format = update_post_data.body_format.unwrap_or(post.body_format);
+            let rendered = render_body(&update_post_data.body, new_format);
 
-                    })),
+            let edit = PostEdit {
+                changes: UpdatePost {
+                    title: Some(update_post_data.title),
+                    body: Some(update_post_data.body),
+                    body_format: Some(new_format),
+                    rendered_body: Some(Some(rendered)),
+                    slug: Some(new_slug),
+                    ..UpdatePost::default()
+                },
+                tags: new_tags,
+                categories: new_categories,
+                revision_by: Some(user.user_id),
+            };
+
+            data.posts
+                .update(&post, edit)?
+                .ok_or_else(precondition_failed)
+        })
+        .await?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(ETag(post_etag(&post)))
+        .json(serde_json::json!({
+            "success": format!("Post successfully updated with id {}", post.id),
+            "slug": post.slug,
+        })))
+}
+
+/// Changes only the fields present in the body and returns the updated post.
+#[patch("/posts/{post_id}")]
+async fn patch_post(
+    data: Data<AppState>,
+    path: Path<String>,
+    patch_body: Json<PatchPostRequest>,
+    req: HttpRequest,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let post_id = path.into_inner();
+    let patch_post_data = patch_body.into_inner();
+    patch_post_data.validate()?;
+
+    let (new_tags, new_categories) =
+        requested_terms(&patch_post_data.tags, &patch_post_data.categories)
+            .map_err(ApiError::BadRequest)?;
+
+    let post = find_post(&data, &post_id).await?;
+
+    user.authorize_owned(
+        post.user_id.as_deref(),
+        Permission::EditOwnPost,
+        Permission::EditAnyPost,
+        "update this post",
+    )?;
+
+    let publish_change = patch_post_data
+        .published
+        .filter(|&published| published != post.is_public());
+    if publish_change.is_some() {
+        user.authorize_owned(
+            post.user_id.as_deref(),
+            Permission::PublishOwnPost,
+            Permission::PublishAnyPost,
+            "change the status of this post",
+        )?;
+    }
+
+    if !if_match_passes(&req, &post) {
+        return Err(precondition_failed());
+    }
+
+    let post = data
+        .blocking(move |data| {
+            let mut changes = UpdatePost::default();
+
+            if patch_post_data.title.is_some() || patch_post_data.slug.is_some() {
+                let new_title = patch_post_data.title.as_deref().unwrap_or(&post.title);
+                let new_slug = choose_slug(
+                    data.posts.as_ref(),
+                    patch_post_data.slug.as_deref(),
+                    new_title,
+                    Some(&post),
+                )?;
+                if new_slug != post.slug {
+                    changes.slug = Some(new_slug);
                 }
             }
-            Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "An error occurred while connecting to the database",
 
-            })),
-        }
-    } else {
-        HttpResponse::Unauthorized().json(serde_json::json!({
-            "error": "Missing or invalid token"
-        }))
-    }
+            let content_changed = patch_post_data.title.is_some()
+                || patch_post_data.body.is_some()
+                || patch_post_data.body_format.is_some();
+            if patch_post_data.body.is_some() || patch_post_data.body_format.is_some() {
+                let new_body = patch_post_data.body.as_deref().unwrap_or(&post.body);
+                let new_format = patch_post_data.body_format.unwrap_or(post.body_format);
+                changes.rendered_body = Some(Some(render_body(new_body, new_format)));
+            }
+
+            match publish_change {
+                Some(true) => {
+                    changes.status = Some(PostStatus::Published);
+                    changes.published_at = Some(Some(Utc::now().naive_utc()));
+                }
+                Some(false) => {
+                    changes.status = Some(PostStatus::Draft);
+                    changes.published_at = Some(None);
+                }
+                None => {}
+            }
+
+            changes.title = patch_post_data.title;
+            changes.body = patch_post_data.body;
+            changes.body_format = patch_post_data.body_format;
+            changes.excerpt = patch_post_data.excerpt;
+            // A cover set by URL replaces any uploaded one.
+            if patch_post_data.cover_image_url.is_some() {
+                changes.cover_media_id = Some(None);
+            }
+            changes.cover_image_url = patch_post_data.cover_image_url;
+
+            let edit = PostEdit {
+                changes,
+                tags: new_tags,
+                categories: new_categories,
+                revision_by: content_changed.then_some(user.user_id),
+            };
+
+            data.posts
+                .update(&post, edit)?
+                .ok_or_else(precondition_failed)
+        })
+        .await?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(ETag(post_etag(&post)))
+        .json(post))
 }
 
-#[delete("/posts/{post_id}/delete")]
-async fn delete_post(data: Data<AppState>, path: Path<String>, req: HttpRequest) -> impl Responder {
-    use crate::db::schema::posts::dsl::{id, posts};
+#[derive(Deserialize, Debug)]
+pub struct SetCoverRequest {
+    /// An uploaded image, or `null` to remove the cover.
+    pub media_id: Option<String>,
+}
 
+/// Uses an uploaded image as the post's cover. The image must belong to the
+/// user or the post's author, unless the user can manage anyone's media.
+#[put("/posts/{post_id}/cover")]
+async fn set_post_cover(
+    data: Data<AppState>,
+    path: Path<String>,
+    cover_body: Json<SetCoverRequest>,
+    req: HttpRequest,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
     let post_id = path.into_inner();
+    let cover_request = cover_body.into_inner();
+
+    let post = find_post(&data, &post_id).await?;
+
+    user.authorize_owned(
+        post.user_id.as_deref(),
+        Permission::EditOwnPost,
+        Permission::EditAnyPost,
+        "update this post",
+    )?;
 
-    if let Some(authenticated_user_id) = req.extensions().get::<String>() {
-        match data.pool.get() {
-            Ok(mut conn) => {
-                let post_exists = posts.filter(id.eq(&post_id)).first::<Post>(&mut conn);
-
-                match post_exists {
-                    Ok(post) => {
-                        if post.user_id == Some(authenticated_user_id.to_string()) {
-                            let deleted_post =
-                                diesel::delete(posts.find(&post_id)).execute(&mut conn);
-
-                            match deleted_post {
-                                Ok(_) => HttpResponse::Ok().json(serde_json::json!({
-                                    "success": format!("Post successfully deleted with id {}", post_id)
-                                })),
-                                Err(e) => {
-                                    HttpResponse::InternalServerError().json(serde_json::json!({
-                                        "error": format!("An error occurred while deleting the post. Error:- {}", e)
-                            
-                                    }))
-                                }
-                            }
-                        } else {
-                            HttpResponse::Unauthorized().json(serde_json::json!({
-                                "error": "You do not have permission to delete this post",
-                       
-                            }))
-                        }
+    if !if_match_passes(&req, &post) {
+        return Err(precondition_failed());
+    }
+
+    let updated_post = data
+        .blocking(move |data| {
+            let cover = match &cover_request.media_id {
+                Some(media_id) => {
+                    let mut conn = data.conn()?;
+                    let cover_media = media::table
+                        .find(media_id)
+                        .select(Media::as_select())
+                        .first::<Media>(&mut conn)
+                        .optional()?
+                        .ok_or_else(|| {
+                            ApiError::NotFound(format!("Media with id {} not found", media_id))
+                        })?;
+
+                    if !cover_media.is_image() {
+                        return Err(ApiError::BadRequest(
+                            "Only images can be used as a cover".into(),
+                        ));
                     }
-                    Err(_) => HttpResponse::NotFound().json(serde_json::json!({
-                        "error": format!("Post with id {} not found", post_id)
-                    })),
+                    let usable = cover_media.user_id.is_some()
+                        && (cover_media.user_id.as_deref() == Some(user.user_id.as_str())
+                            || cover_media.user_id == post.user_id);
+                    if !usable {
+                        user.authorize(Permission::ManageAnyMedia, "use this media")?;
+                    }
+
+                    Some((
+                        cover_media.id.clone(),
+                        data.storage.url(&cover_media.storage_key),
+                    ))
                 }
+                None => None,
+            };
+            let (new_media_id, new_url) = cover.unzip();
+
+            let edit = PostEdit {
+                changes: UpdatePost {
+                    cover_media_id: Some(new_media_id),
+                    cover_image_url: Some(new_url),
+                    ..UpdatePost::default()
+                },
+                ..PostEdit::default()
+            };
+
+            data.posts
+                .update(&post, edit)?
+                .ok_or_else(precondition_failed)
+        })
+        .await?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(ETag(post_etag(&updated_post)))
+        .json(updated_post))
+}
+
+/// Moves the post to the trash. It stays restorable until it is purged, by
+/// hand or once the retention period runs out.
+#[delete("/posts/{post_id}/delete")]
+async fn delete_post(
+    data: Data<AppState>,
+    path: Path<String>,
+    req: HttpRequest,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let post_id = path.into_inner();
+    let post = find_post(&data, &post_id).await?;
+
+    user.authorize_owned(
+        post.user_id.as_deref(),
+        Permission::DeleteOwnPost,
+        Permission::DeleteAnyPost,
+        "delete this post",
+    )?;
+
+    if !if_match_passes(&req, &post) {
+        return Err(precondition_failed());
+    }
+
+    let trashed = data
+        .blocking({
+            let post_id = post_id.clone();
+            move |data| Ok(data.posts.trash(&post_id, post.version)?)
+        })
+        .await?;
+    if !trashed {
+        return Err(precondition_failed());
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": format!("Post moved to trash with id {}", post_id)
+    })))
+}
+
+/// Trashed posts, most recently deleted first. Users see the posts they could
+/// delete: their own, or every post for those allowed to delete any.
+#[get("/posts/trash")]
+async fn get_trash(
+    data: Data<AppState>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    user.authorize(Permission::DeleteOwnPost, "view the trash")?;
+
+    let trashed_posts = data
+        .blocking(move |data| {
+            let author_id =
+                Some(user.user_id.as_str()).filter(|_| !user.role.can(Permission::DeleteAnyPost));
+            Ok(data.posts.list_trash(author_id)?)
+        })
+        .await?;
+
+    Ok(HttpResponse::Ok().json(trashed_posts))
+}
+
+/// Loads a trashed post and checks the user could have deleted it.
+fn trashed_post(
+    posts: &dyn PostRepository,
+    post_id: &str,
+    user: &AuthenticatedUser,
+    action: &str,
+) -> Result<Post, ApiError> {
+    let post = posts
+        .find_trashed(post_id)?
+        .ok_or_else(|| not_in_trash(post_id))?;
+
+    user.authorize_owned(
+        post.user_id.as_deref(),
+        Permission::DeleteOwnPost,
+        Permission::DeleteAnyPost,
+        action,
+    )?;
+    Ok(post)
+}
+
+fn not_in_trash(post_id: &str) -> ApiError {
+    ApiError::NotFound(format!("Post with id {} not found in the trash", post_id))
+}
+
+/// Takes a post back out of the trash, in the state it was deleted in.
+#[post("/posts/{post_id}/restore")]
+async fn restore_post(
+    data: Data<AppState>,
+    path: Path<String>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let post_id = path.into_inner();
+
+    let post = data
+        .blocking(move |data| {
+            trashed_post(data.posts.as_ref(), &post_id, &user, "restore this post")?;
+
+            data.posts
+                .restore(&post_id)?
+                .ok_or_else(|| not_in_trash(&post_id))
+        })
+        .await?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(ETag(post_etag(&post)))
+        .json(post))
+}
+
+/// Permanently deletes a trashed post along with its comments, revisions and
+/// term assignments.
+#[delete("/posts/{post_id}/purge")]
+async fn purge_post(
+    data: Data<AppState>,
+    path: Path<String>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let post_id = path.into_inner();
+
+    data.blocking({
+        let post_id = post_id.clone();
+        move |data| {
+            trashed_post(data.posts.as_ref(), &post_id, &user, "purge this post")?;
+
+            // Restored in the meantime means there is nothing left to purge.
+            if !data.posts.purge(&post_id)? {
+                return Err(not_in_trash(&post_id));
             }
-            Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "An error occurred while connecting to the database"
-            })),
+            Ok(())
         }
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": format!("Post permanently deleted with id {}", post_id)
+    })))
+}
+
+/// Every write to a post bumps its version, which doubles as its ETag.
+fn post_etag(post: &Post) -> EntityTag {
+    EntityTag::new_strong(format!("v{}", post.version))
+}
+
+/// `false` when the request carries an `If-Match` that doesn't name the post's
+/// current version.
+fn if_match_passes(req: &HttpRequest, post: &Post) -> bool {
+    if !req.headers().contains_key(header::IF_MATCH) {
+        return true;
+    }
+
+    match IfMatch::parse(req) {
+        Ok(IfMatch::Any) => true,
+        Ok(IfMatch::Items(tags)) => tags.iter().any(|tag| tag.strong_eq(&post_etag(post))),
+        Err(_) => false,
+    }
+}
+
+/// Whether the copy the client names in `If-None-Match` is still current.
+fn not_modified(req: &HttpRequest, etag: &EntityTag) -> bool {
+    if !req.headers().contains_key(header::IF_NONE_MATCH) {
+        return false;
+    }
+
+    match IfNoneMatch::parse(req) {
+        Ok(IfNoneMatch::Any) => true,
+        Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        Err(_) => false,
+    }
+}
+
+fn precondition_failed() -> ApiError {
+    ApiError::PreconditionFailed("The post has been modified since it was fetched".into())
+}
+
+/// Unpublished posts are only visible to people who could edit them; everyone
+/// else gets a 404 so drafts don't leak their existence.
+pub fn can_view_post(post: &Post, user: Option<&AuthenticatedUser>) -> bool {
+    post.is_public()
+        || user.is_some_and(|user| {
+            user.authorize_owned(
+                post.user_id.as_deref(),
+                Permission::EditOwnPost,
+                Permission::EditAnyPost,
+                "view this post",
+            )
+            .is_ok()
+        })
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PublishPostRequest {
+    /// When set to a future time the post is scheduled instead of published
+    /// immediately.
+    pub publish_at: Option<DateTime<Utc>>,
+}
+
+#[post("/posts/{post_id}/publish")]
+async fn publish_post(
+    data: Data<AppState>,
+    path: Path<String>,
+    body: Option<Json<PublishPostRequest>>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let now = Utc::now().naive_utc();
+    let publish_at = body
+        .and_then(|body| body.into_inner().publish_at)
+        .map(|publish_at| publish_at.naive_utc())
+        .unwrap_or(now);
+
+    let new_status = if publish_at > now {
+        PostStatus::Scheduled
     } else {
-        HttpResponse::Unauthorized().json(serde_json::json!({
-            "error": "Missing or invalid token",
-  
-        }))
+        PostStatus::Published
+    };
+
+    change_post_status(&data, path.into_inner(), &user, new_status, |_| {
+        Some(publish_at)
+    })
+    .await
+}
+
+#[post("/posts/{post_id}/unpublish")]
+async fn unpublish_post(
+    data: Data<AppState>,
+    path: Path<String>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    change_post_status(&data, path.into_inner(), &user, PostStatus::Draft, |_| None).await
+}
+
+#[post("/posts/{post_id}/archive")]
+async fn archive_post(
+    data: Data<AppState>,
+    path: Path<String>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    // Archived posts keep their original publication date.
+    change_post_status(
+        &data,
+        path.into_inner(),
+        &user,
+        PostStatus::Archived,
+        |post| post.published_at,
+    )
+    .await
+}
+
+async fn change_post_status(
+    data: &AppState,
+    post_id: String,
+    user: &AuthenticatedUser,
+    new_status: PostStatus,
+    new_published_at: impl FnOnce(&Post) -> Option<NaiveDateTime>,
+) -> Result<HttpResponse, ApiError> {
+    let post = find_post(data, &post_id).await?;
+
+    user.authorize_owned(
+        post.user_id.as_deref(),
+        Permission::PublishOwnPost,
+        Permission::PublishAnyPost,
+        "change the status of this post",
+    )?;
+
+    let new_published_at = new_published_at(&post);
+    let post = data
+        .blocking(move |data| {
+            data.posts
+                .set_status(&post_id, new_status, new_published_at)?
+                .ok_or_else(|| post_not_found(&post_id))
+        })
+        .await?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(ETag(post_etag(&post)))
+        .json(post))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        db::models::{CreatePost, Role},
+        repositories::memory::{InMemoryPostRepository, InMemoryUserRepository},
+    };
+    use actix_web::{http::StatusCode, test, App, HttpMessage};
+    use chrono::SubsecRound;
+    use std::sync::Arc;
+
+    #[actix_web::test]
+    async fn trash_rejects_a_stale_version() {
+        let posts = Arc::new(InMemoryPostRepository::new());
+        let post = posts
+            .create(
+                CreatePost::new(
+                    "Stale".into(),
+                    "Body".into(),
+                    BodyFormat::Markdown,
+                    "author".into(),
+                    "stale".into(),
+                ),
+                None,
+                None,
+            )
+            .unwrap();
+        // Publishing moves the post to v2 behind the client's back.
+        posts
+            .set_status(&post.id, PostStatus::Published, None)
+            .unwrap();
+
+        let state = AppState::in_memory(posts.clone(), Arc::new(InMemoryUserRepository::new()));
+        let app =
+            test::init_service(App::new().app_data(Data::new(state)).service(delete_post)).await;
+        let trash_request = |version: &str| {
+            let req = test::TestRequest::delete()
+                .uri(&format!("/posts/{}/delete", post.id))
+                .insert_header((header::IF_MATCH, format!("\"{}\"", version)))
+                .to_request();
+            req.extensions_mut().insert(AuthenticatedUser {
+                user_id: "author".into(),
+                session_id: "session".into(),
+                role: Role::Author,
+            });
+            req
+        };
+
+        let res = test::call_service(&app, trash_request("v1")).await;
+        assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+        assert!(posts.find(&post.id).unwrap().is_some());
+
+        let res = test::call_service(&app, trash_request("v2")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(posts.find(&post.id).unwrap().is_none());
+    }
+
+    /// A post as the database would hold it, for seeding states the handlers
+    /// can't reach directly.
+    fn seeded_post(id: &str, author: &str, slug: &str) -> Post {
+        Post {
+            id: id.into(),
+            title: format!("Seeded post {}", id),
+            body: "Seeded body".into(),
+            user_id: Some(author.into()),
+            created_at: Utc::now().naive_utc().trunc_subsecs(6),
+            updated_at: None,
+            status: PostStatus::Draft,
+            published_at: None,
+            slug: slug.into(),
+            body_format: BodyFormat::Markdown,
+            rendered_body: None,
+            version: 1,
+            excerpt: None,
+            cover_image_url: None,
+            deleted_at: None,
+            cover_media_id: None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn post_lifecycle_runs_without_a_database() {
+        let posts = Arc::new(InMemoryPostRepository::new());
+        let state = AppState::in_memory(posts.clone(), Arc::new(InMemoryUserRepository::new()));
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(state))
+                .service(get_post_by_slug)
+                .service(get_trash)
+                .service(get_post)
+                .service(create_post)
+                .service(patch_post)
+                .service(delete_post)
+                .service(restore_post)
+                .service(purge_post)
+                .service(publish_post),
+        )
+        .await;
+        let as_author = |req: test::TestRequest| {
+            let req = req.to_request();
+            req.extensions_mut().insert(AuthenticatedUser {
+                user_id: "author".into(),
+                session_id: "session".into(),
+                role: Role::Author,
+            });
+            req
+        };
+
+        let req = test::TestRequest::post()
+            .uri("/posts/create")
+            .set_json(serde_json::json!({
+                "title": "Lifecycle of a post",
+                "body": "Some body text",
+                "tags": ["Rust", "Testing"],
+            }));
+        let res = test::call_service(&app, as_author(req)).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let created: serde_json::Value = test::read_body_json(res).await;
+        assert_eq!(created["slug"], "lifecycle-of-a-post");
+        let post_id = posts
+            .find_by_slug("lifecycle-of-a-post")
+            .unwrap()
+            .unwrap()
+            .id;
+
+        // Drafts are hidden from everyone who couldn't edit them.
+        let req = test::TestRequest::get().uri(&format!("/posts/{}", post_id));
+        let res = test::call_service(&app, req.to_request()).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::post().uri(&format!("/posts/{}/publish", post_id));
+        let res = test::call_service(&app, as_author(req)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get().uri(&format!("/posts/{}", post_id));
+        let res = test::call_service(&app, req.to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let detail: serde_json::Value = test::read_body_json(res).await;
+        assert_eq!(detail["tags"][0]["slug"], "rust");
+        assert_eq!(detail["tags"][1]["slug"], "testing");
+
+        let req = test::TestRequest::patch()
+            .uri(&format!("/posts/{}", post_id))
+            .set_json(serde_json::json!({ "slug": "renamed" }));
+        let res = test::call_service(&app, as_author(req)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get().uri("/posts/by-slug/lifecycle-of-a-post");
+        let res = test::call_service(&app, req.to_request()).await;
+        assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(
+            res.headers().get(header::LOCATION).unwrap(),
+            "/posts/by-slug/renamed"
+        );
+
+        let req = test::TestRequest::delete().uri(&format!("/posts/{}/delete", post_id));
+        let res = test::call_service(&app, as_author(req)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get().uri("/posts/by-slug/renamed");
+        let res = test::call_service(&app, req.to_request()).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::post().uri(&format!("/posts/{}/restore", post_id));
+        let res = test::call_service(&app, as_author(req)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(posts.find(&post_id).unwrap().is_some());
+
+        let req = test::TestRequest::delete().uri(&format!("/posts/{}/delete", post_id));
+        let res = test::call_service(&app, as_author(req)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = test::TestRequest::delete().uri(&format!("/posts/{}/purge", post_id));
+        let res = test::call_service(&app, as_author(req)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(posts.find_trashed(&post_id).unwrap().is_none());
+
+        let req = test::TestRequest::get().uri("/posts/trash");
+        let res = test::call_service(&app, as_author(req)).await;
+        let trash: Vec<serde_json::Value> = test::read_body_json(res).await;
+        assert!(trash.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn trash_is_limited_to_posts_the_user_could_delete() {
+        let posts = Arc::new(InMemoryPostRepository::new());
+        for (id, author) in [("own", "author"), ("other", "someone-else")] {
+            posts.insert(seeded_post(id, author, id));
+            posts.trash(id, 1).unwrap();
+        }
+        let state = AppState::in_memory(posts.clone(), Arc::new(InMemoryUserRepository::new()));
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(state))
+                .service(get_trash)
+                .service(restore_post),
+        )
+        .await;
+        let as_author = |req: test::TestRequest| {
+            let req = req.to_request();
+            req.extensions_mut().insert(AuthenticatedUser {
+                user_id: "author".into(),
+                session_id: "session".into(),
+                role: Role::Author,
+            });
+            req
+        };
+
+        let res = test::call_service(
+            &app,
+            as_author(test::TestRequest::get().uri("/posts/trash")),
+        )
+        .await;
+        let trash: Vec<serde_json::Value> = test::read_body_json(res).await;
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0]["id"], "own");
+
+        let req = test::TestRequest::post().uri("/posts/other/restore");
+        let res = test::call_service(&app, as_author(req)).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        assert!(posts.find_trashed("other").unwrap().is_some());
+    }
+
+    #[actix_web::test]
+    async fn former_slugs_redirect_to_the_current_one() {
+        let posts = Arc::new(InMemoryPostRepository::new());
+        posts.insert(seeded_post("moved", "author", "new-home"));
+        posts.insert_former_slug("old-home", "moved");
+        posts
+            .set_status("moved", PostStatus::Published, Some(Utc::now().naive_utc()))
+            .unwrap();
+        let state = AppState::in_memory(posts, Arc::new(InMemoryUserRepository::new()));
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(state))
+                .service(get_post_by_slug),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/posts/by-slug/old-home");
+        let res = test::call_service(&app, req.to_request()).await;
+        assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(
+            res.headers().get(header::LOCATION).unwrap(),
+            "/posts/by-slug/new-home"
+        );
+
+        let req = test::TestRequest::get().uri("/posts/by-slug/new-home");
+        let res = test::call_service(&app, req.to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().contains_key(header::ETAG));
+    }
+
+    #[actix_web::test]
+    async fn listing_pages_by_cursor_and_filters_by_tag() {
+        let posts = Arc::new(InMemoryPostRepository::new());
+        let listed = [
+            ("first", "First listed post", vec!["Rust"]),
+            ("second", "Second listed post", vec![]),
+            ("third", "Third listed post", vec!["Rust"]),
+        ];
+        for (days_ago, (id, title, tags)) in listed.into_iter().rev().enumerate() {
+            let mut post = seeded_post(id, "author", id);
+            post.title = title.into();
+            post.status = PostStatus::Published;
+            post.created_at -= chrono::Duration::days(days_ago as i64);
+            posts.insert(post.clone());
+
+            let tags: Vec<String> = tags.into_iter().map(String::from).collect();
+            let edit = PostEdit {
+                tags: Some(normalize_terms(&tags).unwrap()),
+                ..PostEdit::default()
+            };
+            posts.update(&post, edit).unwrap();
+        }
+        let state = AppState::in_memory(posts, Arc::new(InMemoryUserRepository::new()));
+        let app =
+            test::init_service(App::new().app_data(Data::new(state)).service(get_posts)).await;
+
+        let req = test::TestRequest::get().uri("/posts?limit=2&order=asc");
+        let res = test::call_service(&app, req.to_request()).await;
+        let page: serde_json::Value = test::read_body_json(res).await;
+        assert_eq!(page["total"], 3);
+        assert_eq!(page["posts"][0]["title"], "First listed post");
+        assert_eq!(page["posts"].as_array().unwrap().len(), 2);
+
+        let req = test::TestRequest::get().uri(&format!(
+            "/posts?limit=2&order=asc&after={}",
+            page["next_cursor"].as_str().unwrap()
+        ));
+        let res = test::call_service(&app, req.to_request()).await;
+        let page: serde_json::Value = test::read_body_json(res).await;
+        assert_eq!(page["posts"][0]["title"], "Third listed post");
+        assert!(page["next_cursor"].is_null());
+        assert!(page["prev_cursor"].is_string());
+
+        let req = test::TestRequest::get().uri("/posts?tag=rust");
+        let res = test::call_service(&app, req.to_request()).await;
+        let page: serde_json::Value = test::read_body_json(res).await;
+        assert_eq!(page["total"], 2);
+        assert_eq!(page["posts"][0]["tags"][0]["name"], "Rust");
+    }
+
+    #[actix_web::test]
+    async fn patch_rejects_invalid_fields_and_accepts_clearing_them() {
+        let posts = Arc::new(InMemoryPostRepository::new());
+        posts.insert(seeded_post("patched", "author", "patched"));
+        let state = AppState::in_memory(posts.clone(), Arc::new(InMemoryUserRepository::new()));
+        let app =
+            test::init_service(App::new().app_data(Data::new(state)).service(patch_post)).await;
+        let patch = |body: serde_json::Value| {
+            let req = test::TestRequest::patch()
+                .uri("/posts/patched")
+                .set_json(body)
+                .to_request();
+            req.extensions_mut().insert(AuthenticatedUser {
+                user_id: "author".into(),
+                session_id: "session".into(),
+                role: Role::Author,
+            });
+            req
+        };
+
+        for (body, field) in [
+            (serde_json::json!({ "title": "x".repeat(81) }), "title"),
+            (serde_json::json!({ "excerpt": "" }), "excerpt"),
+            (
+                serde_json::json!({ "cover_image_url": "not a url" }),
+                "cover_image_url",
+            ),
+        ] {
+            let res = test::call_service(&app, patch(body)).await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+            let body: serde_json::Value = test::read_body_json(res).await;
+            assert_eq!(body["error"]["fields"][0]["field"], field);
+        }
+        assert_eq!(posts.find("patched").unwrap().unwrap().version, 1);
+
+        let res = test::call_service(
+            &app,
+            patch(serde_json::json!({ "excerpt": null, "cover_image_url": null })),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::OK);
     }
 }
@@ -0,0 +1,319 @@
+use actix_web::{
+    get, post,
+    web::{Data, Path, Query},
+    HttpResponse,
+};
+use chrono::NaiveDateTime;
+use diesel::{
+    Connection, ExpressionMethods, NullableExpressionMethods, OptionalExtension, PgConnection,
+    QueryDsl, QueryResult, RunQueryDsl, SelectableHelper,
+};
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+
+use crate::{
+    db::{
+        connection::AppState,
+        models::{BodyFormat, Post, PostRevision},
+        schema::{post_revisions, posts, users},
+    },
+    errors::ApiError,
+    middlewares::{auth::AuthenticatedUser, permissions::Permission},
+    repositories::postgres::snapshot_revision,
+    services::posts::post_not_found,
+    utils::render::render_body,
+};
+
+/// Lines of unchanged context around each hunk of the unified diff.
+const DIFF_CONTEXT_LINES: usize = 3;
+
+#[derive(Serialize, Debug)]
+pub struct RevisionSummary {
+    pub id: String,
+    pub editor_id: Option<String>,
+    pub editor_name: Option<String>,
+    pub title: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RevisionDiffQuery {
+    pub from: String,
+    /// A revision id, or `current` for the post as it is now. Defaults to
+    /// `current`.
+    pub to: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DiffLine {
+    /// One of `equal`, `insert` or `delete`.
+    pub op: &'static str,
+    pub old_line: Option<usize>,
+    pub new_line: Option<usize>,
+    pub text: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RevisionDiff {
+    pub from: String,
+    pub to: String,
+    pub old_title: String,
+    pub new_title: String,
+    pub body_format_changed: bool,
+    pub unified: String,
+    pub lines: Vec<DiffLine>,
+}
+
+/// The content half of a post or revision, whichever side of a diff it is on.
+struct Snapshot {
+    title: String,
+    body: String,
+    body_format: BodyFormat,
+}
+
+/// Loads the post and checks the user could edit it; revisions expose drafts
+/// of the content, so they are limited to editors of the post.
+async fn editable_post(
+    data: &AppState,
+    post_id: &str,
+    user: &AuthenticatedUser,
+) -> Result<Post, ApiError> {
+    let post = data
+        .with_conn({
+            let post_id = post_id.to_string();
+            move |conn| {
+                posts::table
+                    .find(post_id)
+                    .filter(posts::deleted_at.is_null())
+                    .select(Post::as_select())
+                    .first::<Post>(conn)
+                    .optional()
+            }
+        })
+        .await?
+        .ok_or_else(|| post_not_found(post_id))?;
+
+    user.authorize_owned(
+        post.user_id.as_deref(),
+        Permission::EditOwnPost,
+        Permission::EditAnyPost,
+        "access this post's revisions",
+    )?;
+    Ok(post)
+}
+
+fn revision_not_found(revision_id: &str) -> ApiError {
+    ApiError::NotFound(format!("Revision with id {} not found", revision_id))
+}
+
+fn find_revision(
+    conn: &mut PgConnection,
+    post_id: &str,
+    revision_id: &str,
+) -> QueryResult<Option<PostRevision>> {
+    post_revisions::table
+        .find(revision_id)
+        .filter(post_revisions::post_id.eq(post_id))
+        .select(PostRevision::as_select())
+        .first::<PostRevision>(conn)
+        .optional()
+}
+
+/// Revisions of a post, newest first.
+#[get("/posts/{post_id}/revisions")]
+async fn get_revisions(
+    data: Data<AppState>,
+    path: Path<String>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let post_id = path.into_inner();
+
+    editable_post(&data, &post_id, &user).await?;
+
+    let revisions = data
+        .with_conn(move |conn| {
+            post_revisions::table
+                .left_join(users::table)
+                .filter(post_revisions::post_id.eq(&post_id))
+                .order((post_revisions::created_at.desc(), post_revisions::id.desc()))
+                .select((
+                    post_revisions::id,
+                    post_revisions::editor_id,
+                    users::name.nullable(),
+                    post_revisions::title,
+                    post_revisions::created_at,
+                ))
+                .load::<(
+                    String,
+                    Option<String>,
+                    Option<String>,
+                    String,
+                    NaiveDateTime,
+                )>(conn)
+        })
+        .await?;
+
+    let summaries: Vec<RevisionSummary> = revisions
+        .into_iter()
+        .map(
+            |(id, editor_id, editor_name, title, created_at)| RevisionSummary {
+                id,
+                editor_id,
+                editor_name,
+                title,
+                created_at,
+            },
+        )
+        .collect();
+    Ok(HttpResponse::Ok().json(summaries))
+}
+
+/// Line diff of the body between two revisions, or a revision and the
+/// current content.
+#[get("/posts/{post_id}/revisions/diff")]
+async fn diff_revisions(
+    data: Data<AppState>,
+    path: Path<String>,
+    query: Query<RevisionDiffQuery>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let post_id = path.into_inner();
+    let diff_query = query.into_inner();
+    let to = diff_query.to.unwrap_or_else(|| "current".into());
+
+    let post = editable_post(&data, &post_id, &user).await?;
+
+    let snapshots = data
+        .with_conn({
+            let from = diff_query.from.clone();
+            let to = to.clone();
+            move |conn| {
+                let mut snapshot = |revision_id: &str| -> QueryResult<Option<Snapshot>> {
+                    if revision_id == "current" {
+                        return Ok(Some(Snapshot {
+                            title: post.title.clone(),
+                            body: post.body.clone(),
+                            body_format: post.body_format,
+                        }));
+                    }
+
+                    Ok(
+                        find_revision(conn, &post_id, revision_id)?.map(|revision| Snapshot {
+                            title: revision.title,
+                            body: revision.body,
+                            body_format: revision.body_format,
+                        }),
+                    )
+                };
+
+                Ok((snapshot(&from)?, snapshot(&to)?))
+            }
+        })
+        .await?;
+
+    let (Some(old), Some(new)) = snapshots else {
+        return Err(ApiError::NotFound(
+            "Revision not found for this post".into(),
+        ));
+    };
+
+    let diff = TextDiff::from_lines(&old.body, &new.body);
+    let unified = diff
+        .unified_diff()
+        .context_radius(DIFF_CONTEXT_LINES)
+        .header(&diff_query.from, &to)
+        .to_string();
+    let lines = diff
+        .iter_all_changes()
+        .map(|change| DiffLine {
+            op: match change.tag() {
+                ChangeTag::Equal => "equal",
+                ChangeTag::Insert => "insert",
+                ChangeTag::Delete => "delete",
+            },
+            old_line: change.old_index().map(|index| index + 1),
+            new_line: change.new_index().map(|index| index + 1),
+            text: change.to_string_lossy().trim_end_matches('\n').to_string(),
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(RevisionDiff {
+        from: diff_query.from,
+        to,
+        body_format_changed: old.body_format != new.body_format,
+        old_title: old.title,
+        new_title: new.title,
+        unified,
+        lines,
+    }))
+}
+
+#[get("/posts/{post_id}/revisions/{revision_id}")]
+async fn get_revision(
+    data: Data<AppState>,
+    path: Path<(String, String)>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let (post_id, revision_id) = path.into_inner();
+
+    editable_post(&data, &post_id, &user).await?;
+
+    let revision = data
+        .with_conn({
+            let revision_id = revision_id.clone();
+            move |conn| find_revision(conn, &post_id, &revision_id)
+        })
+        .await?
+        .ok_or_else(|| revision_not_found(&revision_id))?;
+
+    Ok(HttpResponse::Ok().json(revision))
+}
+
+/// Makes a revision's content current again. The content being replaced is
+/// itself saved as a revision, so a restore can be undone. The slug is kept.
+#[post("/posts/{post_id}/revisions/{revision_id}/restore")]
+async fn restore_revision(
+    data: Data<AppState>,
+    path: Path<(String, String)>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let (post_id, revision_id) = path.into_inner();
+
+    let post = editable_post(&data, &post_id, &user).await?;
+
+    let revision = data
+        .with_conn({
+            let post_id = post_id.clone();
+            let revision_id = revision_id.clone();
+            move |conn| find_revision(conn, &post_id, &revision_id)
+        })
+        .await?
+        .ok_or_else(|| revision_not_found(&revision_id))?;
+
+    let restored = data
+        .with_conn(move |conn| {
+            conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                snapshot_revision(conn, &post, &user.user_id)?;
+
+                diesel::update(posts::table.find(&post_id))
+                    .set((
+                        posts::title.eq(&revision.title),
+                        posts::body.eq(&revision.body),
+                        posts::body_format.eq(revision.body_format),
+                        posts::rendered_body.eq(render_body(&revision.body, revision.body_format)),
+                        posts::version.eq(posts::version + 1),
+                    ))
+                    .returning(Post::as_returning())
+                    .get_result::<Post>(conn)
+            })
+        })
+        .await
+        .map_err(|e| match e {
+            ApiError::Conflict(_) => {
+                ApiError::Conflict("Another post already uses this revision's title".into())
+            }
+            e => e,
+        })?;
+
+    Ok(HttpResponse::Ok().json(restored))
+}
@@ -0,0 +1,253 @@
+use actix_web::{
+    get,
+    web::{Data, Query},
+    HttpResponse,
+};
+use chrono::NaiveDateTime;
+use diesel::{
+    sql_query,
+    sql_types::{BigInt, Bool, Float, Nullable, Text, Timestamp},
+    QueryableByName, RunQueryDsl,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::{connection::AppState, models::PostStatus},
+    errors::ApiError,
+    middlewares::{auth::OptionalUser, permissions::Permission},
+    utils::render::escape_html,
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 20;
+const MAX_PAGE_SIZE: i64 = 100;
+
+// ts_headline marks matches with these; the text is escaped before they are
+// swapped for <mark> tags, so post bodies can't inject markup into snippets.
+const HIGHLIGHT_START: &str = "\u{2}";
+const HIGHLIGHT_STOP: &str = "\u{3}";
+
+#[derive(Deserialize, Debug)]
+pub struct SearchQuery {
+    /// Words must all match. `"quoted words"` match as a phrase, `word*`
+    /// matches as a prefix and `-word` excludes posts containing it.
+    pub q: String,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(QueryableByName, Debug)]
+struct SearchRow {
+    #[diesel(sql_type = Text)]
+    id: String,
+    #[diesel(sql_type = Text)]
+    slug: String,
+    #[diesel(sql_type = Text)]
+    title: String,
+    #[diesel(sql_type = Text)]
+    title_highlight: String,
+    #[diesel(sql_type = Text)]
+    snippet: String,
+    #[diesel(sql_type = Float)]
+    rank: f32,
+    #[diesel(sql_type = Nullable<Text>)]
+    user_id: Option<String>,
+    #[diesel(sql_type = Text)]
+    status: PostStatus,
+    #[diesel(sql_type = Timestamp)]
+    created_at: NaiveDateTime,
+    #[diesel(sql_type = Nullable<Timestamp>)]
+    published_at: Option<NaiveDateTime>,
+}
+
+#[derive(QueryableByName, Debug)]
+struct SearchCount {
+    #[diesel(sql_type = BigInt)]
+    total: i64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SearchHit {
+    pub id: String,
+    pub slug: String,
+    pub title: String,
+    /// HTML: the escaped title with matches wrapped in `<mark>`.
+    pub title_highlight: String,
+    /// HTML: escaped excerpts of the body with matches wrapped in `<mark>`.
+    pub snippet: String,
+    pub rank: f32,
+    pub user_id: Option<String>,
+    pub status: PostStatus,
+    pub created_at: NaiveDateTime,
+    pub published_at: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SearchResults {
+    pub results: Vec<SearchHit>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+/// Turns the user's search into a `to_tsquery` expression. Only letters and
+/// digits survive from each word, so the input can't smuggle in tsquery
+/// operators. Returns `None` when nothing searchable is left.
+fn build_tsquery(input: &str) -> Option<String> {
+    let clean = |word: &str| -> String { word.chars().filter(|c| c.is_alphanumeric()).collect() };
+    let mut terms = Vec::new();
+
+    // Odd segments between double quotes are phrases.
+    for (i, segment) in input.split('"').enumerate() {
+        if i % 2 == 1 {
+            let words: Vec<String> = segment
+                .split_whitespace()
+                .map(clean)
+                .filter(|word| !word.is_empty())
+                .collect();
+            if !words.is_empty() {
+                terms.push(format!("({})", words.join(" <-> ")));
+            }
+            continue;
+        }
+
+        for token in segment.split_whitespace() {
+            let (negated, token) = match token.strip_prefix('-') {
+                Some(rest) => (true, rest),
+                None => (false, token),
+            };
+            let (prefix, token) = match token.strip_suffix('*') {
+                Some(rest) => (true, rest),
+                None => (false, token),
+            };
+
+            let word = clean(token);
+            if word.is_empty() {
+                continue;
+            }
+            terms.push(format!(
+                "{}{}{}",
+                if negated { "!" } else { "" },
+                word,
+                if prefix { ":*" } else { "" }
+            ));
+        }
+    }
+
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" & "))
+    }
+}
+
+fn highlight(text: &str) -> String {
+    escape_html(text)
+        .replace(HIGHLIGHT_START, "<mark>")
+        .replace(HIGHLIGHT_STOP, "</mark>")
+}
+
+/// Ranked full-text search. Anonymous callers only see published posts;
+/// signed-in users also see unpublished posts they could edit.
+#[get("/posts/search")]
+async fn search_posts(
+    data: Data<AppState>,
+    query: Query<SearchQuery>,
+    OptionalUser(user): OptionalUser,
+) -> Result<HttpResponse, ApiError> {
+    let search_query = query.into_inner();
+    let limit = search_query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let offset = search_query.offset.unwrap_or(0).max(0);
+
+    let tsquery = build_tsquery(&search_query.q).ok_or_else(|| {
+        ApiError::BadRequest("Search query must contain at least one letter or digit".into())
+    })?;
+
+    let see_all = user
+        .as_ref()
+        .is_some_and(|user| user.role.can(Permission::EditAnyPost));
+    let own_posts_of = user
+        .as_ref()
+        .filter(|user| user.role.can(Permission::EditOwnPost))
+        .map(|user| user.user_id.clone());
+
+    let total = data
+        .with_conn({
+            let tsquery = tsquery.clone();
+            let own_posts_of = own_posts_of.clone();
+            move |conn| {
+                sql_query(
+                    "SELECT count(*) AS total \
+                     FROM posts \
+                     WHERE search_vector @@ to_tsquery('english', $1) \
+                       AND deleted_at IS NULL \
+                       AND (status = 'published' OR $2 OR user_id = $3)",
+                )
+                .bind::<Text, _>(tsquery)
+                .bind::<Bool, _>(see_all)
+                .bind::<Nullable<Text>, _>(own_posts_of)
+                .get_result::<SearchCount>(conn)
+            }
+        })
+        .await?
+        .total;
+
+    let headline_options = format!(
+        "StartSel={}, StopSel={}, MaxFragments=2, MaxWords=30, MinWords=10, FragmentDelimiter=\" … \"",
+        HIGHLIGHT_START, HIGHLIGHT_STOP
+    );
+    let title_headline_options = format!(
+        "StartSel={}, StopSel={}, HighlightAll=true",
+        HIGHLIGHT_START, HIGHLIGHT_STOP
+    );
+
+    let rows = data
+        .with_conn(move |conn| {
+            sql_query(
+                "SELECT p.id, p.slug, p.title, p.user_id, p.status, p.created_at, p.published_at, \
+                        ts_rank_cd(p.search_vector, q.query) AS rank, \
+                        ts_headline('english', p.title, q.query, $6) AS title_highlight, \
+                        ts_headline('english', p.body, q.query, $7) AS snippet \
+                 FROM posts p, to_tsquery('english', $1) AS q(query) \
+                 WHERE p.search_vector @@ q.query \
+                   AND p.deleted_at IS NULL \
+                   AND (p.status = 'published' OR $2 OR p.user_id = $3) \
+                 ORDER BY rank DESC, p.created_at DESC, p.id DESC \
+                 LIMIT $4 OFFSET $5",
+            )
+            .bind::<Text, _>(tsquery)
+            .bind::<Bool, _>(see_all)
+            .bind::<Nullable<Text>, _>(own_posts_of)
+            .bind::<BigInt, _>(limit)
+            .bind::<BigInt, _>(offset)
+            .bind::<Text, _>(title_headline_options)
+            .bind::<Text, _>(headline_options)
+            .load::<SearchRow>(conn)
+        })
+        .await?;
+
+    let results = rows
+        .into_iter()
+        .map(|row| SearchHit {
+            title_highlight: highlight(&row.title_highlight),
+            snippet: highlight(&row.snippet),
+            id: row.id,
+            slug: row.slug,
+            title: row.title,
+            rank: row.rank,
+            user_id: row.user_id,
+            status: row.status,
+            created_at: row.created_at,
+            published_at: row.published_at,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(SearchResults {
+        results,
+        total,
+        limit,
+        offset,
+    }))
+}
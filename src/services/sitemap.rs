@@ -0,0 +1,166 @@
+use actix_web::{
+    get,
+    http::header::{CacheControl, CacheDirective},
+    web::{Data, Path},
+    HttpResponse, Responder,
+};
+use chrono::{NaiveDateTime, SecondsFormat};
+use diesel::{ExpressionMethods, PgConnection, QueryDsl, QueryResult, RunQueryDsl};
+use std::env;
+
+use crate::{
+    db::{connection::AppState, models::PostStatus, schema::posts},
+    errors::ApiError,
+    utils::{
+        render::escape_html,
+        site::{api_url, post_url},
+    },
+};
+
+/// The most URLs the sitemap protocol allows in one file. Past this,
+/// `/sitemap.xml` becomes an index of numbered sitemaps.
+const SITEMAP_PAGE_SIZE: i64 = 50_000;
+const SITEMAP_MAX_AGE: u32 = 3600;
+
+fn published_posts_count(conn: &mut PgConnection) -> QueryResult<i64> {
+    posts::table
+        .filter(posts::status.eq(PostStatus::Published))
+        .filter(posts::deleted_at.is_null())
+        .count()
+        .get_result(conn)
+}
+
+/// One page of `(slug, lastmod)` pairs starting at `offset`, oldest posts
+/// first so that pages stay stable as new posts are published.
+fn published_posts_page(
+    conn: &mut PgConnection,
+    offset: i64,
+) -> QueryResult<Vec<(String, NaiveDateTime)>> {
+    let rows = posts::table
+        .filter(posts::status.eq(PostStatus::Published))
+        .filter(posts::deleted_at.is_null())
+        .order((posts::created_at.asc(), posts::id.asc()))
+        .limit(SITEMAP_PAGE_SIZE)
+        .offset(offset)
+        .select((
+            posts::slug,
+            posts::updated_at,
+            posts::published_at,
+            posts::created_at,
+        ))
+        .load::<(
+            String,
+            Option<NaiveDateTime>,
+            Option<NaiveDateTime>,
+            NaiveDateTime,
+        )>(conn)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(slug, updated_at, published_at, created_at)| {
+            (slug, updated_at.or(published_at).unwrap_or(created_at))
+        })
+        .collect())
+}
+
+fn urlset(entries: &[(String, NaiveDateTime)]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for (slug, lastmod) in entries {
+        xml.push_str(&format!(
+            "<url><loc>{}</loc><lastmod>{}</lastmod></url>\n",
+            escape_html(&post_url(slug)),
+            lastmod.and_utc().to_rfc3339_opts(SecondsFormat::Secs, true),
+        ));
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
+
+fn sitemap_index(base_url: &str, pages: i64) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for page in 1..=pages {
+        xml.push_str(&format!(
+            "<sitemap><loc>{}</loc></sitemap>\n",
+            escape_html(&format!("{}/sitemaps/{}.xml", base_url, page)),
+        ));
+    }
+    xml.push_str("</sitemapindex>\n");
+    xml
+}
+
+fn xml_response(body: String) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/xml; charset=utf-8")
+        .insert_header(CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(SITEMAP_MAX_AGE),
+        ]))
+        .body(body)
+}
+
+/// Every published post, or an index of numbered sitemaps once there are
+/// more than fit in one.
+#[get("/sitemap.xml")]
+async fn get_sitemap(data: Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let count = data.with_conn(published_posts_count).await?;
+
+    if count > SITEMAP_PAGE_SIZE {
+        let pages = (count + SITEMAP_PAGE_SIZE - 1) / SITEMAP_PAGE_SIZE;
+        return Ok(xml_response(sitemap_index(&api_url(), pages)));
+    }
+
+    let entries = data.with_conn(|conn| published_posts_page(conn, 0)).await?;
+    Ok(xml_response(urlset(&entries)))
+}
+
+/// One page of a split sitemap, numbered from 1.
+#[get("/sitemaps/{page}.xml")]
+async fn get_sitemap_page(data: Data<AppState>, path: Path<i64>) -> Result<HttpResponse, ApiError> {
+    let page = path.into_inner();
+    let page_not_found = || ApiError::NotFound(format!("Sitemap {} not found", page));
+    // Pages too far out to have an offset can't have any posts either.
+    let offset = page
+        .checked_sub(1)
+        .filter(|index| *index >= 0)
+        .and_then(|index| index.checked_mul(SITEMAP_PAGE_SIZE))
+        .ok_or_else(page_not_found)?;
+
+    let entries = data
+        .with_conn(move |conn| published_posts_page(conn, offset))
+        .await?;
+    if entries.is_empty() && page > 1 {
+        return Err(page_not_found());
+    }
+    Ok(xml_response(urlset(&entries)))
+}
+
+/// Crawler rules. `ROBOTS_DISALLOW` takes comma-separated path prefixes to
+/// keep crawlers out of; by default everything may be crawled.
+#[get("/robots.txt")]
+async fn get_robots() -> impl Responder {
+    let disallowed = env::var("ROBOTS_DISALLOW").unwrap_or_default();
+
+    let mut robots = String::from("User-agent: *\n");
+    let mut rules = disallowed
+        .split(',')
+        .map(str::trim)
+        .filter(|path| !path.is_empty())
+        .peekable();
+    if rules.peek().is_none() {
+        robots.push_str("Disallow:\n");
+    }
+    for path in rules {
+        robots.push_str(&format!("Disallow: {}\n", path));
+    }
+    robots.push_str(&format!("\nSitemap: {}/sitemap.xml\n", api_url()));
+
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .insert_header(CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(SITEMAP_MAX_AGE),
+        ]))
+        .body(robots)
+}
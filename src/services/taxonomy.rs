@@ -0,0 +1,353 @@
+use actix_web::{
+    get, post, put,
+    web::{Data, Json, Path, Query},
+    HttpResponse,
+};
+use diesel::{
+    dsl::count_star, Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl,
+    QueryResult, RunQueryDsl,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    db::{
+        connection::AppState,
+        models::{PostStatus, Term},
+        schema::{categories, post_categories, post_tags, posts, tags},
+    },
+    errors::ApiError,
+    middlewares::{
+        auth::{AuthenticatedUser, OptionalUser},
+        permissions::Permission,
+    },
+    services::posts::{list_posts, PostListQuery},
+    utils::slug::slugify,
+};
+
+const MAX_TERM_NAME_LENGTH: usize = 50;
+
+#[derive(Serialize, Debug)]
+pub struct TermCount {
+    pub name: String,
+    pub slug: String,
+    /// Number of published posts carrying the term.
+    pub post_count: i64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RenameTagRequest {
+    pub name: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MergeTagRequest {
+    /// Slug of the tag that absorbs this one.
+    pub into: String,
+}
+
+/// Trims requested tag or category names and pairs them with their slugs,
+/// dropping names that collapse to the same slug.
+pub fn normalize_terms(names: &[String]) -> Result<Vec<Term>, String> {
+    let mut seen = HashSet::new();
+    let mut terms = Vec::new();
+
+    for name in names {
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > MAX_TERM_NAME_LENGTH {
+            return Err(format!(
+                "Tag and category names must be between 1 and {} characters",
+                MAX_TERM_NAME_LENGTH
+            ));
+        }
+
+        let slug = slugify(name);
+        if slug.is_empty() {
+            return Err(format!(
+                "Tag or category name {} must contain a letter or digit",
+                name
+            ));
+        }
+
+        if seen.insert(slug.clone()) {
+            terms.push(Term {
+                name: name.to_string(),
+                slug,
+            });
+        }
+    }
+
+    Ok(terms)
+}
+
+/// Terms without a published post could name the topic of a draft, so only
+/// those who can edit every post or manage terms see them.
+fn sees_unpublished_terms(user: Option<&AuthenticatedUser>) -> bool {
+    user.is_some_and(|user| {
+        user.role.can(Permission::EditAnyPost) || user.role.can(Permission::ManageTaxonomy)
+    })
+}
+
+#[get("/tags")]
+async fn get_tags(
+    data: Data<AppState>,
+    OptionalUser(user): OptionalUser,
+) -> Result<HttpResponse, ApiError> {
+    let include_unpublished = sees_unpublished_terms(user.as_ref());
+    let (all_tags, counts) = data
+        .with_conn(|conn| {
+            let all_tags = tags::table
+                .order(tags::name.asc())
+                .select((tags::id, tags::name, tags::slug))
+                .load::<(String, String, String)>(conn)?;
+            let counts = post_tags::table
+                .inner_join(posts::table)
+                .filter(posts::status.eq(PostStatus::Published))
+                .filter(posts::deleted_at.is_null())
+                .group_by(post_tags::tag_id)
+                .select((post_tags::tag_id, count_star()))
+                .load::<(String, i64)>(conn)?;
+            Ok((all_tags, counts))
+        })
+        .await?;
+
+    let counts: HashMap<String, i64> = counts.into_iter().collect();
+    let tag_counts: Vec<TermCount> = all_tags
+        .into_iter()
+        .map(|(tag_id, name, slug)| TermCount {
+            post_count: counts.get(&tag_id).copied().unwrap_or(0),
+            name,
+            slug,
+        })
+        .filter(|term| include_unpublished || term.post_count > 0)
+        .collect();
+    Ok(HttpResponse::Ok().json(tag_counts))
+}
+
+#[get("/categories")]
+async fn get_categories(
+    data: Data<AppState>,
+    OptionalUser(user): OptionalUser,
+) -> Result<HttpResponse, ApiError> {
+    let include_unpublished = sees_unpublished_terms(user.as_ref());
+    let (all_categories, counts) = data
+        .with_conn(|conn| {
+            let all_categories = categories::table
+                .order(categories::name.asc())
+                .select((categories::id, categories::name, categories::slug))
+                .load::<(String, String, String)>(conn)?;
+            let counts = post_categories::table
+                .inner_join(posts::table)
+                .filter(posts::status.eq(PostStatus::Published))
+                .filter(posts::deleted_at.is_null())
+                .group_by(post_categories::category_id)
+                .select((post_categories::category_id, count_star()))
+                .load::<(String, i64)>(conn)?;
+            Ok((all_categories, counts))
+        })
+        .await?;
+
+    let counts: HashMap<String, i64> = counts.into_iter().collect();
+    let category_counts: Vec<TermCount> = all_categories
+        .into_iter()
+        .map(|(category_id, name, slug)| TermCount {
+            post_count: counts.get(&category_id).copied().unwrap_or(0),
+            name,
+            slug,
+        })
+        .filter(|term| include_unpublished || term.post_count > 0)
+        .collect();
+    Ok(HttpResponse::Ok().json(category_counts))
+}
+
+/// The post listing narrowed to one tag; accepts the same query parameters
+/// as `GET /posts`.
+#[get("/tags/{slug}/posts")]
+async fn get_tag_posts(
+    data: Data<AppState>,
+    path: Path<String>,
+    query: Query<PostListQuery>,
+    OptionalUser(user): OptionalUser,
+) -> Result<HttpResponse, ApiError> {
+    let tag_slug = path.into_inner();
+
+    data.with_conn({
+        let tag_slug = tag_slug.clone();
+        move |conn| {
+            tags::table
+                .filter(tags::slug.eq(tag_slug))
+                .select(tags::id)
+                .first::<String>(conn)
+                .optional()
+        }
+    })
+    .await?
+    .ok_or_else(|| ApiError::NotFound(format!("Tag {} not found", tag_slug)))?;
+
+    let mut list_query = query.into_inner();
+    list_query.tag = Some(tag_slug);
+    list_posts(&data, list_query, user).await
+}
+
+/// The post listing narrowed to one category; accepts the same query
+/// parameters as `GET /posts`.
+#[get("/categories/{slug}/posts")]
+async fn get_category_posts(
+    data: Data<AppState>,
+    path: Path<String>,
+    query: Query<PostListQuery>,
+    OptionalUser(user): OptionalUser,
+) -> Result<HttpResponse, ApiError> {
+    let category_slug = path.into_inner();
+
+    data.with_conn({
+        let category_slug = category_slug.clone();
+        move |conn| {
+            categories::table
+                .filter(categories::slug.eq(category_slug))
+                .select(categories::id)
+                .first::<String>(conn)
+                .optional()
+        }
+    })
+    .await?
+    .ok_or_else(|| ApiError::NotFound(format!("Category {} not found", category_slug)))?;
+
+    let mut list_query = query.into_inner();
+    list_query.category = Some(category_slug);
+    list_posts(&data, list_query, user).await
+}
+
+#[put("/tags/{slug}")]
+async fn rename_tag(
+    data: Data<AppState>,
+    path: Path<String>,
+    body: Json<RenameTagRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    user.authorize(Permission::ManageTaxonomy, "rename tags")?;
+
+    let tag_slug = path.into_inner();
+    let term = normalize_terms(&[body.into_inner().name])
+        .map_err(ApiError::BadRequest)?
+        .remove(0);
+
+    let renamed = data
+        .with_conn({
+            let tag_slug = tag_slug.clone();
+            let term = term.clone();
+            move |conn| {
+                conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                    let Some(tag_id) = diesel::update(tags::table.filter(tags::slug.eq(tag_slug)))
+                        .set((tags::name.eq(term.name), tags::slug.eq(term.slug)))
+                        .returning(tags::id)
+                        .get_result::<String>(conn)
+                        .optional()?
+                    else {
+                        return Ok(false);
+                    };
+
+                    let post_ids = post_tags::table
+                        .filter(post_tags::tag_id.eq(&tag_id))
+                        .select(post_tags::post_id)
+                        .load::<String>(conn)?;
+                    bump_post_versions(conn, &post_ids)?;
+
+                    Ok(true)
+                })
+            }
+        })
+        .await
+        .map_err(|e| match e {
+            ApiError::Conflict(_) => ApiError::Conflict(
+                "A tag with this name already exists, merge the tags instead".into(),
+            ),
+            e => e,
+        })?;
+
+    if !renamed {
+        return Err(ApiError::NotFound(format!("Tag {} not found", tag_slug)));
+    }
+    Ok(HttpResponse::Ok().json(term))
+}
+
+/// Post ETags cover their term names, so posts whose tags were renamed or
+/// merged get a new version.
+fn bump_post_versions(conn: &mut PgConnection, post_ids: &[String]) -> QueryResult<usize> {
+    diesel::update(posts::table.filter(posts::id.eq_any(post_ids)))
+        .set(posts::version.eq(posts::version + 1))
+        .execute(conn)
+}
+
+/// Moves every post from one tag to another and deletes the emptied tag.
+#[post("/tags/{slug}/merge")]
+async fn merge_tag(
+    data: Data<AppState>,
+    path: Path<String>,
+    body: Json<MergeTagRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    user.authorize(Permission::ManageTaxonomy, "merge tags")?;
+
+    let source_slug = path.into_inner();
+    let target_slug = body.into_inner().into;
+
+    if source_slug == target_slug {
+        return Err(ApiError::BadRequest(
+            "A tag cannot be merged into itself".into(),
+        ));
+    }
+
+    let moved = data
+        .with_conn({
+            let source_slug = source_slug.clone();
+            let target_slug = target_slug.clone();
+            move |conn| {
+                conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                    let find_tag = |conn: &mut PgConnection, slug: &str| {
+                        tags::table
+                            .filter(tags::slug.eq(slug))
+                            .select(tags::id)
+                            .first::<String>(conn)
+                            .optional()
+                    };
+                    let (Some(source_id), Some(target_id)) =
+                        (find_tag(conn, &source_slug)?, find_tag(conn, &target_slug)?)
+                    else {
+                        return Ok(None);
+                    };
+
+                    let post_ids = post_tags::table
+                        .filter(post_tags::tag_id.eq(&source_id))
+                        .select(post_tags::post_id)
+                        .load::<String>(conn)?;
+                    let rows: Vec<_> = post_ids
+                        .iter()
+                        .map(|post_id| {
+                            (
+                                post_tags::post_id.eq(post_id),
+                                post_tags::tag_id.eq(&target_id),
+                            )
+                        })
+                        .collect();
+
+                    if !rows.is_empty() {
+                        diesel::insert_into(post_tags::table)
+                            .values(rows)
+                            .on_conflict_do_nothing()
+                            .execute(conn)?;
+                    }
+                    diesel::delete(tags::table.find(&source_id)).execute(conn)?;
+                    bump_post_versions(conn, &post_ids)?;
+
+                    Ok(Some(post_ids.len()))
+                })
+            }
+        })
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Both tags must exist to merge them".into()))?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": format!("Merged tag {} into {} ({} posts)", source_slug, target_slug, moved)
+    })))
+}
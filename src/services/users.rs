@@ -3,25 +3,33 @@ use actix_web::{
         time::{Duration, OffsetDateTime},
         Cookie, SameSite,
     },
-    get, post,
-    web::{Data, Json},
-    HttpMessage, HttpRequest, HttpResponse, Responder,
-};
-use diesel::{
-    query_dsl::methods::{FilterDsl, FindDsl, SelectDsl},
-    result::{DatabaseErrorKind, Error::DatabaseError},
-    ExpressionMethods, OptionalExtension, RunQueryDsl, SelectableHelper,
+    get,
+    http::header,
+    post, put,
+    web::{Data, Json, Path, Query},
+    HttpRequest, HttpResponse,
 };
+use chrono::{DateTime, Utc};
+use jsonwebtoken::errors::ErrorKind;
 use serde::Deserialize;
 use validator::Validate;
 
 use crate::{
     db::{
         connection::AppState,
-        models::{CreateUser, User},
+        models::{CreateRefreshToken, CreateSession, CreateUser, Role, User},
+    },
+    errors::ApiError,
+    mail::{
+        send::MailOptions,
+        templates::{password_reset::password_reset_template, verification::verification_template},
+    },
+    middlewares::{auth::AuthenticatedUser, permissions::Permission},
+    repositories::{RefreshOutcome, UserRepository},
+    utils::hashing::{
+        decode_jwt, generate_jwt, generate_refresh_token, generate_session_jwt, hash_password,
+        hash_refresh_token, verify_password, JwtMETHODS, REFRESH_TOKEN_TTL_DAYS,
     },
-    mail::{send::MailOptions, templates::verification::verification_template},
-    utils::hashing::{decode_jwt, generate_jwt, verify_password, JwtMETHODS},
 };
 
 #[derive(Deserialize, Validate, Debug)]
@@ -43,107 +51,37 @@ pub struct RegisterRequest {
 }
 
 #[post("/users/register")]
-async fn register(data: Data<AppState>, body: Json<RegisterRequest>) -> impl Responder {
-    use crate::db::schema::users::dsl::users;
+async fn register(
+    data: Data<AppState>,
+    body: Json<RegisterRequest>,
+) -> Result<HttpResponse, ApiError> {
     let register_data = body.into_inner();
+    register_data.validate()?;
 
-    // Validate the request body
-    if let Err(validation_errors) = register_data.validate() {
-        let register_error_messages: Vec<String> = validation_errors
-            .field_errors()
-            .into_iter()
-            .map(|(field, errors)| {
-                format!(
-                    "Invalid {}: {}",
-                    field,
-                    errors
-                        .into_iter()
-                        .map(|e| e.message.clone().unwrap_or_else(|| "Invalid value".into()))
-                        .collect::<Vec<_>>()
-                        .join(", ")
-                )
-            })
-            .collect();
+    data.blocking(move |data| {
+        if data.users.find_by_email(&register_data.email)?.is_some() {
+            return Err(ApiError::Conflict("User already exists".into()));
+        }
 
-        return HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Validation error(s)",
-            "details": register_error_messages,
-        }));
-    }
+        let new_user = CreateUser::new(
+            register_data.name,
+            register_data.email,
+            register_data.password,
+        )?;
 
-    let mut conn = match data.pool.get() {
-        Ok(conn) => conn,
-        Err(_) => {
-            return HttpResponse::InternalServerError()
-                .json(serde_json::json!({"error": "Database connection error"}));
-        }
-    };
+        // Signed before the insert: a failure here must not leave behind an
+        // account whose email can't be registered again.
+        let mail = verification_mail(&new_user.id, &new_user.name, &new_user.email)?;
+        data.users.create(new_user)?;
 
-    let existing_user = users
-        .find(&register_data.email)
-        .select(User::as_select())
-        .first(&mut conn)
-        .optional();
-
-    match existing_user {
-        Ok(Some(_)) => HttpResponse::Conflict().json(serde_json::json!({
-            "error": "User already exists"
-        })),
-        Ok(None) => {
-            let new_user = CreateUser::new(
-                register_data.name,
-                register_data.email,
-                register_data.password,
-            );
-
-            match new_user {
-                Ok(user) => {
-                    match diesel::insert_into(users)
-                        .values(user)
-                        .returning(User::as_returning())
-                        .get_result::<User>(&mut conn)
-                    {
-                        Ok(user) => {
-                            let token = generate_jwt(user.id, JwtMETHODS::Default).unwrap();
-                            let (
-                                subject,
-                                to,
-                                html,
-                                smtp_verification_name,
-                                smtp_verification_email,
-                            ) = verification_template(user.name, user.email, token);
-                            let options = MailOptions {
-                                user_name: smtp_verification_name,
-                                user_email: smtp_verification_email,
-                                to,
-                                subject,
-                                html_content: html,
-                            };
-                            MailOptions::send(options);
-                            HttpResponse::Created().json(serde_json::json!({
-                                "message": "User created successfully"
-                            }))
-                        }
-                        Err(DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
-                            HttpResponse::Conflict().json(serde_json::json!({
-                                "error": "User already exists"
-                            }))
-                        }
-                        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-                            "error": "An error occurred while creating the user"
-                        })),
-                    }
-                }
-                Err(e) => HttpResponse::BadRequest().json(serde_json::json!({
-                    "error": "Invalid data",
-                    "message": format!("An error occurred: {}", e)
-                })),
-            }
-        }
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "An error occurred while registering the user"
-        })),
-    }
+        mail.send();
+        Ok(())
+    })
+    .await?;
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "User created successfully"
+    })))
 }
 
 #[derive(Deserialize, Validate, Debug)]
@@ -159,155 +97,713 @@ pub struct LoginRequest {
 }
 
 #[post("/users/login")]
-async fn login(data: Data<AppState>, body: Json<LoginRequest>) -> impl Responder {
-    use crate::db::schema::users::dsl::{email, users};
+async fn login(
+    data: Data<AppState>,
+    body: Json<LoginRequest>,
+    req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
     let login_data = body.into_inner();
+    login_data.validate()?;
+
+    // Unknown emails get the same answer as wrong passwords.
+    let invalid_credentials = || ApiError::Unauthorized("Invalid credentials".into());
+    let user = data
+        .blocking(move |data| {
+            let user = data
+                .users
+                .find_by_email(&login_data.email)?
+                .ok_or_else(invalid_credentials)?;
+            let hashed_password = user.password.clone().ok_or_else(invalid_credentials)?;
+            verify_password(login_data.password, hashed_password)?;
+            Ok(user)
+        })
+        .await?;
+
+    let user_agent = req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from);
+    let ip_address = req.connection_info().realip_remote_addr().map(String::from);
+    let new_session = CreateSession::new(user.id.to_string(), user_agent, ip_address);
+
+    let refresh_token = generate_refresh_token();
+    let new_refresh_token = CreateRefreshToken::new(
+        new_session.id.to_string(),
+        hash_refresh_token(&refresh_token),
+        Utc::now().naive_utc() + chrono::Duration::days(REFRESH_TOKEN_TTL_DAYS),
+    );
+
+    let session_id = new_session.id.clone();
+    data.blocking(move |data| Ok(data.users.create_session(new_session, new_refresh_token)?))
+        .await?;
 
-    // Validate the request body
-    if let Err(validation_errors) = login_data.validate() {
-        let login_error_messages: Vec<String> = validation_errors
-            .field_errors()
+    let access_token = generate_session_jwt(user.id, session_id, JwtMETHODS::Access)?;
+    Ok(HttpResponse::Ok()
+        .cookie(refresh_cookie(
+            refresh_token,
+            OffsetDateTime::now_utc() + Duration::days(REFRESH_TOKEN_TTL_DAYS),
+        ))
+        .json(serde_json::json!({
+            "token": access_token
+        })))
+}
+
+fn refresh_cookie(value: String, expires: OffsetDateTime) -> Cookie<'static> {
+    Cookie::build("refresh_token", value)
+        .http_only(true)
+        .secure(true)
+        .domain("rishabhportfolio.site")
+        .expires(expires)
+        .same_site(SameSite::Lax)
+        .path("/users/token")
+        .finish()
+}
+
+#[post("/users/token/refresh")]
+async fn refresh_access_token(
+    data: Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let cookie = req
+        .cookie("refresh_token")
+        .ok_or_else(|| ApiError::Unauthorized("No refresh token received".into()))?;
+    let presented_hash = hash_refresh_token(cookie.value());
+
+    let refresh_token = generate_refresh_token();
+    let replacement_hash = hash_refresh_token(&refresh_token);
+    let expires_at = Utc::now().naive_utc() + chrono::Duration::days(REFRESH_TOKEN_TTL_DAYS);
+
+    let outcome = data
+        .blocking(move |data| {
+            Ok(data
+                .users
+                .rotate_refresh_token(&presented_hash, &replacement_hash, expires_at)?)
+        })
+        .await?;
+
+    match outcome {
+        RefreshOutcome::Rotated {
+            user_id,
+            session_id,
+        } => {
+            let access_token = generate_session_jwt(user_id, session_id, JwtMETHODS::Access)?;
+            Ok(HttpResponse::Ok()
+                .cookie(refresh_cookie(
+                    refresh_token,
+                    OffsetDateTime::now_utc() + Duration::days(REFRESH_TOKEN_TTL_DAYS),
+                ))
+                .json(serde_json::json!({
+                    "token": access_token
+                })))
+        }
+        RefreshOutcome::Reused => Err(ApiError::Unauthorized(
+            "Refresh token reuse detected, the session has been revoked".into(),
+        )),
+        RefreshOutcome::Invalid => Err(ApiError::Unauthorized(
+            "Invalid or expired refresh token".into(),
+        )),
+    }
+}
+
+#[get("/users/logout")]
+async fn logout(data: Data<AppState>, user: AuthenticatedUser) -> Result<HttpResponse, ApiError> {
+    data.blocking(move |data| {
+        data.users.revoke_session(&user.session_id, &user.user_id)?;
+        Ok(())
+    })
+    .await?;
+
+    // Set an expired date so the browser drops the refresh token
+    let cookie = refresh_cookie(String::new(), OffsetDateTime::now_utc() - Duration::days(1));
+    Ok(HttpResponse::Ok().cookie(cookie).json(serde_json::json!({
+      "success": "User logged out successfully!"
+    })))
+}
+
+#[get("/users/sessions")]
+async fn get_sessions(
+    data: Data<AppState>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let active_sessions = data
+        .blocking({
+            let current_user_id = user.user_id.clone();
+            move |data| Ok(data.users.active_sessions(&current_user_id)?)
+        })
+        .await?;
+
+    Ok(HttpResponse::Ok().json(
+        active_sessions
             .into_iter()
-            .map(|(field, errors)| {
-                format!(
-                    "Invalid {}: {}",
-                    field,
-                    errors
-                        .into_iter()
-                        .map(|e| e.message.clone().unwrap_or_else(|| "Invalid value".into()))
-                        .collect::<Vec<_>>()
-                        .join(", ")
-                )
+            .map(|session| {
+                serde_json::json!({
+                    "current": session.id == user.session_id,
+                    "session": session,
+                })
             })
-            .collect();
+            .collect::<Vec<_>>(),
+    ))
+}
 
-        return HttpResponse::BadRequest().json(serde_json::json!({
-            "error": login_error_messages
-        }));
+#[post("/users/sessions/{session_id}/revoke")]
+async fn revoke_session(
+    data: Data<AppState>,
+    path: Path<String>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let session_id = path.into_inner();
+
+    let revoked = data
+        .blocking({
+            let session_id = session_id.clone();
+            move |data| Ok(data.users.revoke_session(&session_id, &user.user_id)?)
+        })
+        .await?;
+
+    if !revoked {
+        return Err(ApiError::NotFound(format!(
+            "Active session with id {} not found",
+            session_id
+        )));
     }
 
-    let mut conn = match data.pool.get() {
-        Ok(conn) => conn,
-        Err(_) => {
-            return HttpResponse::InternalServerError()
-                .json(serde_json::json!({"error": "Database connection error"}));
-        }
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": format!("Session successfully revoked with id {}", session_id)
+    })))
+}
+
+#[post("/users/sessions/revoke-others")]
+async fn revoke_other_sessions(
+    data: Data<AppState>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let revoked = data
+        .blocking(move |data| {
+            Ok(data
+                .users
+                .revoke_other_sessions(&user.user_id, &user.session_id)?)
+        })
+        .await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": format!("Revoked {} other session(s)", revoked)
+    })))
+}
+
+#[get("/users")]
+async fn get_users(
+    data: Data<AppState>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    user.authorize(Permission::ManageUsers, "manage users")?;
+
+    let all_users = data.blocking(|data| Ok(data.users.list()?)).await?;
+    Ok(HttpResponse::Ok().json(all_users))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UpdateRoleRequest {
+    pub role: Role,
+}
+
+#[put("/users/{user_id}/role")]
+async fn update_user_role(
+    data: Data<AppState>,
+    path: Path<String>,
+    body: Json<UpdateRoleRequest>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    user.authorize(Permission::ManageUsers, "manage users")?;
+
+    let target_user_id = path.into_inner();
+    let new_role = body.into_inner().role;
+
+    let updated_user = data
+        .blocking(move |data| {
+            data.users
+                .update_role(&target_user_id, new_role)?
+                .ok_or_else(|| {
+                    ApiError::NotFound(format!("User with id {} not found", target_user_id))
+                })
+        })
+        .await?;
+
+    Ok(HttpResponse::Ok().json(updated_user))
+}
+
+/// Promotes the registered user with `email` to admin, so a fresh install
+/// has someone who can manage roles. Returns `false` when nobody has
+/// registered with that email yet.
+pub fn bootstrap_admin(users: &dyn UserRepository, email: &str) -> Result<bool, ApiError> {
+    let Some(user) = users.find_by_email(email)? else {
+        return Ok(false);
     };
 
-    let existing_user = users
-        .filter(email.eq(&login_data.email))
-        .first::<User>(&mut conn);
-
-    match existing_user {
-        Ok(user) => match verify_password(login_data.password, user.password.unwrap()) {
-            Ok(_) => {
-                let cookie_token = generate_jwt(user.id.to_string(), JwtMETHODS::Default).unwrap();
-                let response_token = generate_jwt(user.id, JwtMETHODS::Login).unwrap();
-                let cookie = Cookie::build("auth_token", cookie_token)
-                    .http_only(true)
-                    .secure(true)
-                    .domain("rishabhportfolio.site")
-                    .expires(OffsetDateTime::now_utc() + Duration::days(7))
-                    .same_site(SameSite::Lax)
-                    .path("/")
-                    .finish();
-                HttpResponse::Ok().cookie(cookie).json(serde_json::json!({
-                    "token": response_token
-                }))
-            }
-            Err(_) => HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Invalid credentials"
-            })),
-        },
-        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": format!("{}", e)
-        })),
+    if user.role != Role::Admin {
+        users.update_role(&user.id, Role::Admin)?;
     }
+    Ok(true)
+}
+
+const VERIFICATION_RESEND_COOLDOWN_MINUTES: i64 = 5;
+
+fn verification_mail(user_id: &str, name: &str, email: &str) -> Result<MailOptions, ApiError> {
+    let token = generate_jwt(user_id.to_string(), JwtMETHODS::EmailVerification)?;
+    let (subject, to, html, smtp_verification_name, smtp_verification_email) =
+        verification_template(name.to_string(), email.to_string(), token);
+    Ok(MailOptions {
+        user_name: smtp_verification_name,
+        user_email: smtp_verification_email,
+        to,
+        subject,
+        html_content: html,
+    })
+}
+
+#[derive(Deserialize, Debug)]
+pub struct VerifyEmailRequest {
+    pub token: String,
+}
+
+#[get("/users/verify")]
+async fn verify_email(
+    data: Data<AppState>,
+    query: Query<VerifyEmailRequest>,
+) -> Result<HttpResponse, ApiError> {
+    verify_email_token(&data, &query.token).await
+}
+
+#[post("/users/verify")]
+async fn confirm_email(
+    data: Data<AppState>,
+    body: Json<VerifyEmailRequest>,
+) -> Result<HttpResponse, ApiError> {
+    verify_email_token(&data, &body.token).await
 }
 
-#[get("/users/check-auth")]
-async fn check_auth(data: Data<AppState>, req: HttpRequest) -> impl Responder {
-    use crate::db::schema::users::dsl::{id, users};
-
-    let user_id_cookie = req.cookie("auth_token");
-
-    if let Some(cookie) = user_id_cookie {
-        let cookie_token = cookie.value();
-
-        match decode_jwt(cookie_token) {
-            Ok(cookie_data) => {
-                let mut conn = match data.pool.get() {
-                    Ok(conn) => conn,
-                    Err(_) => {
-                        return HttpResponse::InternalServerError()
-                            .json(serde_json::json!({"error": "Database connection error"}));
-                    }
-                };
-                let existing_user = users
-                    .filter(id.eq(&cookie_data.sub))
-                    .first::<User>(&mut conn);
-
-                match existing_user {
-                    Ok(user) => {
-                        let response_token = generate_jwt(user.id, JwtMETHODS::Login).unwrap();
-                        return HttpResponse::Ok().cookie(cookie).json(serde_json::json!({
-                            "token": response_token
-                        }));
-                    }
-
-                    Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-                        "error": format!("No user found with this id {}", cookie_data.sub)
-                    })),
-                }
+async fn verify_email_token(data: &AppState, token: &str) -> Result<HttpResponse, ApiError> {
+    let claims =
+        decode_jwt(token, JwtMETHODS::EmailVerification).map_err(|err| match err.kind() {
+            ErrorKind::ExpiredSignature => {
+                ApiError::Gone("Verification link has expired, please request a new one".into())
             }
-            Err(err) => HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": format!("Invalid Cookie received with error:- {}", err)
-            })),
+            _ => ApiError::BadRequest("Invalid verification token".into()),
+        })?;
+
+    data.blocking(move |data| {
+        let user = data.users.find(&claims.sub)?.ok_or_else(|| {
+            ApiError::NotFound("No user found for this verification token".into())
+        })?;
+
+        if user.verified {
+            return Err(ApiError::Conflict("Email is already verified".into()));
         }
-    } else {
-        return HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "No Cookie received"
-        }));
-    }
+
+        Ok(data.users.mark_verified(&user.id)?)
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": "Email verified successfully"
+    })))
 }
 
-#[get("/users/logout")]
-async fn logout(data: Data<AppState>, req: HttpRequest) -> impl Responder {
-    use crate::db::schema::users::dsl::{id, users};
-    if let Some(authenticated_user_id) = req.extensions().get::<String>() {
-        match data.pool.get() {
-            Ok(mut conn) => {
-                let user_id = authenticated_user_id.to_string();
-                let user_exists = users.filter(id.eq(&user_id)).first::<User>(&mut conn);
-                match user_exists {
-                    Ok(_) => {
-                        let cookie = Cookie::build("auth_token", "")
-                            .http_only(true)
-                            .secure(true)
-                            .domain("rishabhportfolio.site") // Adjust domain as needed
-                            .expires(OffsetDateTime::now_utc() - Duration::days(1)) // Set expired date
-                            .same_site(SameSite::Lax)
-                            .path("/")
-                            .finish();
-                        return HttpResponse::Ok().cookie(cookie).json(serde_json::json!({
-                          "success": "User logged out successfully!"
-                        }));
-                    }
-                    Err(_) => {
-                        return HttpResponse::InternalServerError().json(serde_json::json!({
-                            "error": format!("No user found with the provided id {}", user_id)
-                        }))
-                    }
-                }
+#[derive(Deserialize, Validate, Debug)]
+pub struct ResendVerificationRequest {
+    #[validate(email(message = "Invalid email format"))]
+    pub email: String,
+}
+
+#[post("/users/verify/resend")]
+async fn resend_verification(
+    data: Data<AppState>,
+    body: Json<ResendVerificationRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let resend_data = body.into_inner();
+    resend_data.validate()?;
+
+    data.blocking(move |data| {
+        // Unknown, already verified and cooling-down addresses all get the
+        // same response so the endpoint can't be used to probe which emails
+        // are registered.
+        let existing_user = data.users.find_by_email(&resend_data.email)?;
+        let Some(user) = existing_user.filter(|user| !user.verified) else {
+            return Ok(());
+        };
+
+        let now = Utc::now().naive_utc();
+        let cooldown_start = now - chrono::Duration::minutes(VERIFICATION_RESEND_COOLDOWN_MINUTES);
+
+        if data
+            .users
+            .claim_verification_resend(&user.id, cooldown_start, now)?
+        {
+            verification_mail(&user.id, &user.name, &user.email)?.send();
+        }
+        Ok(())
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": "If an unverified account exists for this email, a verification link has been sent"
+    })))
+}
+
+#[derive(Deserialize, Validate, Debug)]
+pub struct ForgotPasswordRequest {
+    #[validate(email(message = "Invalid email format"))]
+    pub email: String,
+}
+
+#[post("/users/password/forgot")]
+async fn forgot_password(
+    data: Data<AppState>,
+    body: Json<ForgotPasswordRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let forgot_data = body.into_inner();
+    forgot_data.validate()?;
+
+    data.blocking(move |data| {
+        if let Some(user) = data.users.find_by_email(&forgot_data.email)? {
+            send_password_reset_mail(user)?;
+        }
+        Ok(())
+    })
+    .await?;
+    // Same response whether or not the account exists.
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": "If an account exists for this email, a password reset link has been sent"
+    })))
+}
+
+fn send_password_reset_mail(user: User) -> Result<(), ApiError> {
+    let token = generate_jwt(user.id, JwtMETHODS::PasswordReset)?;
+    let (subject, to, html, smtp_reset_name, smtp_reset_email) =
+        password_reset_template(user.name, user.email, token);
+    let options = MailOptions {
+        user_name: smtp_reset_name,
+        user_email: smtp_reset_email,
+        to,
+        subject,
+        html_content: html,
+    };
+    MailOptions::send(options);
+    Ok(())
+}
+
+#[derive(Deserialize, Validate, Debug)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    #[validate(length(
+        min = 6,
+        max = 20,
+        message = "Password length must be between 6 and 20 characters"
+    ))]
+    pub password: String,
+}
+
+#[post("/users/password/reset")]
+async fn reset_password(
+    data: Data<AppState>,
+    body: Json<ResetPasswordRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let reset_data = body.into_inner();
+    reset_data.validate()?;
+
+    let invalid_token = || ApiError::BadRequest("Invalid password reset token".into());
+    let claims =
+        decode_jwt(&reset_data.token, JwtMETHODS::PasswordReset).map_err(|err| {
+            match err.kind() {
+                ErrorKind::ExpiredSignature => ApiError::Gone(
+                    "Password reset link has expired, please request a new one".into(),
+                ),
+                _ => invalid_token(),
             }
-            Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "An error occurred while connecting to the database"
-            })),
+        })?;
+
+    let issued_at = DateTime::from_timestamp(claims.iat as i64, 0)
+        .ok_or_else(invalid_token)?
+        .naive_utc();
+
+    let updated = data
+        .blocking(move |data| {
+            let hashed_password = hash_password(reset_data.password)?;
+
+            // A reset token is only honoured if the password hasn't changed since it
+            // was issued, which makes each token single-use. A successful reset
+            // signs the account out everywhere.
+            Ok(data
+                .users
+                .reset_password(&claims.sub, hashed_password, issued_at)?)
+        })
+        .await?;
+
+    if !updated {
+        return Err(ApiError::BadRequest(
+            "This password reset link is no longer valid".into(),
+        ));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": "Password reset successfully"
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::memory::{InMemoryPostRepository, InMemoryUserRepository};
+    use actix_web::{cookie::Cookie, http::StatusCode, test, App, HttpMessage};
+    use std::sync::Arc;
+
+    fn existing_user(email: &str) -> User {
+        User {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: "Existing".into(),
+            email: email.into(),
+            password: None,
+            verified: false,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+            verification_sent_at: Some(Utc::now().naive_utc()),
+            password_changed_at: None,
+            role: Role::Author,
         }
-    } else {
-        HttpResponse::Unauthorized().json(serde_json::json!({
-            "error": "Missing or invalid token",
+    }
+
+    fn state_with(user: User) -> (AppState, Arc<InMemoryUserRepository>) {
+        let users = Arc::new(InMemoryUserRepository::new());
+        users.insert(user);
+        let state = AppState::in_memory(Arc::new(InMemoryPostRepository::new()), users.clone());
+        (state, users)
+    }
+
+    #[actix_web::test]
+    async fn register_rejects_a_taken_email() {
+        let (state, users) = state_with(existing_user("taken@example.com"));
+        let app = test::init_service(App::new().app_data(Data::new(state)).service(register)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/users/register")
+            .set_json(serde_json::json!({
+                "name": "Someone",
+                "email": "taken@example.com",
+                "password": "secret1",
+            }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+        let body: serde_json::Value = test::read_body_json(res).await;
+        assert_eq!(body["error"]["code"], "conflict");
+        assert_eq!(users.list().unwrap().len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn resend_verification_skips_the_send_during_the_cooldown() {
+        let user = existing_user("pending@example.com");
+        let sent_at = user.verification_sent_at;
+        let (state, users) = state_with(user);
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(state))
+                .service(resend_verification),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/users/verify/resend")
+            .set_json(serde_json::json!({ "email": "pending@example.com" }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        let user = users.find_by_email("pending@example.com").unwrap().unwrap();
+        assert_eq!(user.verification_sent_at, sent_at);
+    }
+
+    /// Starts a session for the user whose refresh token is `refresh_token`.
+    fn start_session(users: &InMemoryUserRepository, user_id: &str, refresh_token: &str) -> String {
+        let session = CreateSession::new(user_id.into(), None, None);
+        let session_id = session.id.clone();
+        users
+            .create_session(
+                session,
+                CreateRefreshToken::new(
+                    session_id.clone(),
+                    hash_refresh_token(refresh_token),
+                    Utc::now().naive_utc() + chrono::Duration::days(1),
+                ),
+            )
+            .unwrap();
+        session_id
+    }
 
-        }))
+    #[actix_web::test]
+    async fn sessions_can_be_listed_and_revoked_by_their_owner_only() {
+        let owner = existing_user("owner@example.com");
+        let other = existing_user("other@example.com");
+        let (owner_id, other_id) = (owner.id.clone(), other.id.clone());
+        let (state, users) = state_with(owner);
+        users.insert(other);
+        let current = start_session(&users, &owner_id, "current");
+        start_session(&users, &owner_id, "laptop");
+        let foreign = start_session(&users, &other_id, "foreign");
+
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(state))
+                .service(logout)
+                .service(get_sessions)
+                .service(revoke_other_sessions)
+                .service(revoke_session),
+        )
+        .await;
+        let as_owner = |req: test::TestRequest| {
+            let req = req.to_request();
+            req.extensions_mut().insert(AuthenticatedUser {
+                user_id: owner_id.clone(),
+                session_id: current.clone(),
+                role: Role::Author,
+            });
+            req
+        };
+
+        let res = test::call_service(
+            &app,
+            as_owner(test::TestRequest::get().uri("/users/sessions")),
+        )
+        .await;
+        let sessions: Vec<serde_json::Value> = test::read_body_json(res).await;
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(
+            sessions
+                .iter()
+                .filter(|session| session["current"] == true)
+                .count(),
+            1
+        );
+
+        let req = test::TestRequest::post().uri(&format!("/users/sessions/{}/revoke", foreign));
+        let res = test::call_service(&app, as_owner(req)).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(users.active_sessions(&other_id).unwrap().len(), 1);
+
+        let req = test::TestRequest::post().uri("/users/sessions/revoke-others");
+        let res = test::call_service(&app, as_owner(req)).await;
+        let body: serde_json::Value = test::read_body_json(res).await;
+        assert_eq!(body["success"], "Revoked 1 other session(s)");
+
+        let res = test::call_service(
+            &app,
+            as_owner(test::TestRequest::get().uri("/users/logout")),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(users.active_sessions(&owner_id).unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn reusing_a_refresh_token_revokes_its_session() {
+        let user = existing_user("refresh@example.com");
+        let user_id = user.id.clone();
+        let (state, users) = state_with(user);
+        start_session(&users, &user_id, "first");
+        // The legitimate client already exchanged the token.
+        let rotated = users
+            .rotate_refresh_token(
+                &hash_refresh_token("first"),
+                &hash_refresh_token("second"),
+                Utc::now().naive_utc() + chrono::Duration::days(1),
+            )
+            .unwrap();
+        assert!(matches!(rotated, RefreshOutcome::Rotated { .. }));
+
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(state))
+                .service(refresh_access_token),
+        )
+        .await;
+        let refresh = |token: &str| {
+            test::TestRequest::post()
+                .uri("/users/token/refresh")
+                .cookie(Cookie::new("refresh_token", token.to_string()))
+                .to_request()
+        };
+
+        let res = test::call_service(&app, refresh("first")).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert!(users.active_sessions(&user_id).unwrap().is_empty());
+
+        // The replacement dies with the session.
+        let res = test::call_service(&app, refresh("second")).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let body: serde_json::Value = test::read_body_json(res).await;
+        assert_eq!(body["error"]["message"], "Invalid or expired refresh token");
+    }
+
+    #[actix_web::test]
+    async fn roles_are_managed_by_admins_without_losing_the_last_one() {
+        let mut admin = existing_user("admin@example.com");
+        admin.role = Role::Admin;
+        let admin_id = admin.id.clone();
+        let reader = existing_user("reader@example.com");
+        let reader_id = reader.id.clone();
+        let (state, users) = state_with(admin);
+        users.insert(reader);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(state))
+                .service(get_users)
+                .service(update_user_role),
+        )
+        .await;
+        let signed_in = |req: test::TestRequest, user_id: &str, role: Role| {
+            let req = req.to_request();
+            req.extensions_mut().insert(AuthenticatedUser {
+                user_id: user_id.into(),
+                session_id: "session".into(),
+                role,
+            });
+            req
+        };
+        let set_role = |user_id: &str, role: &str| {
+            test::TestRequest::put()
+                .uri(&format!("/users/{}/role", user_id))
+                .set_json(serde_json::json!({ "role": role }))
+        };
+
+        let req = signed_in(
+            test::TestRequest::get().uri("/users"),
+            &reader_id,
+            Role::Author,
+        );
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let req = signed_in(set_role(&admin_id, "author"), &admin_id, Role::Admin);
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        let req = signed_in(set_role(&reader_id, "editor"), &admin_id, Role::Admin);
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = signed_in(
+            test::TestRequest::get().uri("/users"),
+            &admin_id,
+            Role::Admin,
+        );
+        let res = test::call_service(&app, req).await;
+        let all_users: Vec<serde_json::Value> = test::read_body_json(res).await;
+        let roles: Vec<&str> = all_users
+            .iter()
+            .map(|user| user["role"].as_str().unwrap())
+            .collect();
+        assert_eq!(roles.len(), 2);
+        assert!(roles.contains(&"admin") && roles.contains(&"editor"));
     }
 }
@@ -0,0 +1,118 @@
+use std::{
+    env, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use super::{Storage, StorageResult};
+
+/// Keeps files in a directory on disk; the app serves them itself under
+/// `/media/files/{key}`.
+pub struct LocalStorage {
+    root: PathBuf,
+    public_url: String,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl AsRef<Path>, public_url: &str) -> Self {
+        let root = root.as_ref().to_path_buf();
+        fs::create_dir_all(&root).expect("Failed to create the media directory");
+
+        LocalStorage {
+            root,
+            public_url: public_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Reads `MEDIA_DIR` (default `uploads`) and `MEDIA_PUBLIC_URL` (default
+    /// `/media/files`).
+    pub fn from_env() -> Self {
+        let root = env::var("MEDIA_DIR").unwrap_or_else(|_| "uploads".into());
+        let public_url = env::var("MEDIA_PUBLIC_URL").unwrap_or_else(|_| "/media/files".into());
+
+        LocalStorage::new(root, &public_url)
+    }
+
+    fn path(&self, key: &str) -> StorageResult<PathBuf> {
+        // Keys are generated by the app, but never let one leave the root.
+        if key.is_empty() || key.contains(['/', '\\']) || key.starts_with('.') {
+            return Err(format!("Invalid storage key {}", key).into());
+        }
+
+        Ok(self.root.join(key))
+    }
+}
+
+impl Storage for LocalStorage {
+    fn put(&self, key: &str, bytes: &[u8], _content_type: &str) -> StorageResult<()> {
+        let path = self.path(key)?;
+        // Write beside the target and rename, so readers never see half a file.
+        let partial = path.with_extension("partial");
+        fs::write(&partial, bytes)?;
+        fs::rename(&partial, &path)?;
+
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> StorageResult<Option<Vec<u8>>> {
+        match fs::read(self.path(key)?) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn delete(&self, key: &str) -> StorageResult<()> {
+        match fs::remove_file(self.path(key)?) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn url(&self, key: &str) -> String {
+        format!("{}/{}", self.public_url, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_files() {
+        let root = env::temp_dir().join(format!("local-storage-{}", uuid::Uuid::new_v4()));
+        let storage = LocalStorage::new(&root, "/media/files/");
+
+        storage.put("photo.jpg", b"bytes", "image/jpeg").unwrap();
+        assert_eq!(
+            storage.get("photo.jpg").unwrap().as_deref(),
+            Some(&b"bytes"[..])
+        );
+        assert_eq!(storage.url("photo.jpg"), "/media/files/photo.jpg");
+
+        storage.put("photo.jpg", b"replaced", "image/jpeg").unwrap();
+        assert_eq!(
+            storage.get("photo.jpg").unwrap().as_deref(),
+            Some(&b"replaced"[..])
+        );
+
+        storage.delete("photo.jpg").unwrap();
+        assert_eq!(storage.get("photo.jpg").unwrap(), None);
+        storage.delete("photo.jpg").unwrap();
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn rejects_keys_outside_the_root() {
+        let root = env::temp_dir().join(format!("local-storage-{}", uuid::Uuid::new_v4()));
+        let storage = LocalStorage::new(&root, "/media/files");
+
+        for key in ["", "../secret", "nested/file", "..\\secret", ".hidden"] {
+            assert!(storage.put(key, b"x", "text/plain").is_err(), "{:?}", key);
+        }
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
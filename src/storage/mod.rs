@@ -0,0 +1,34 @@
+pub mod local;
+pub mod s3;
+
+use std::{env, sync::Arc};
+
+use local::LocalStorage;
+use s3::S3Storage;
+
+pub type StorageResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Where uploaded files live. Implementations block, so handlers call them
+/// through `web::block`.
+pub trait Storage: Send + Sync {
+    /// Stores `bytes` under `key`, replacing anything already there.
+    fn put(&self, key: &str, bytes: &[u8], content_type: &str) -> StorageResult<()>;
+
+    /// The stored bytes, or `None` when nothing is stored under `key`.
+    fn get(&self, key: &str) -> StorageResult<Option<Vec<u8>>>;
+
+    /// Removes the object; removing a missing one is not an error.
+    fn delete(&self, key: &str) -> StorageResult<()>;
+
+    /// The URL clients fetch the object from.
+    fn url(&self, key: &str) -> String;
+}
+
+/// Picks the backend from `STORAGE_BACKEND` (`local`, the default, or `s3`).
+pub fn storage_from_env() -> Arc<dyn Storage> {
+    match env::var("STORAGE_BACKEND").as_deref() {
+        Ok("local") | Err(_) => Arc::new(LocalStorage::from_env()),
+        Ok("s3") => Arc::new(S3Storage::from_env()),
+        Ok(other) => panic!("Unknown STORAGE_BACKEND {}, expected local or s3", other),
+    }
+}
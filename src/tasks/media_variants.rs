@@ -0,0 +1,72 @@
+use crate::{
+    db::{
+        connection::DbPool,
+        models::{CreateMediaVariant, Media},
+    },
+    storage::Storage,
+    utils::images::{render_variants, VariantSpec},
+};
+use actix_web::{rt, web};
+use diesel::RunQueryDsl;
+use std::sync::Arc;
+
+/// Sizes rendered when `IMAGE_VARIANTS` isn't set.
+pub const DEFAULT_IMAGE_VARIANTS: &str = "thumbnail:200,medium:800,large:1600";
+
+/// Spawns a background task that renders an uploaded image's variants, so
+/// the upload responds before decoding and resizing are done.
+pub fn spawn_variant_generation(
+    pool: DbPool,
+    storage: Arc<dyn Storage>,
+    specs: Arc<[VariantSpec]>,
+    media: Media,
+) {
+    rt::spawn(async move {
+        let media_id = media.id.clone();
+        let generated =
+            web::block(move || generate_variants(&pool, storage.as_ref(), &specs, &media)).await;
+
+        match generated {
+            Ok(Ok(count)) => eprintln!("Generated {} variant(s) for media {}", count, media_id),
+            Ok(Err(e)) => eprintln!("Failed to generate variants for media {}: {}", media_id, e),
+            Err(e) => eprintln!("Failed to generate variants for media {}: {}", media_id, e),
+        }
+    });
+}
+
+pub fn generate_variants(
+    pool: &DbPool,
+    storage: &dyn Storage,
+    specs: &[VariantSpec],
+    media: &Media,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    use crate::db::schema::media_variants::dsl::media_variants;
+
+    let original = storage
+        .get(&media.storage_key)?
+        .ok_or("The original file is missing from storage")?;
+    let variants = render_variants(&original, specs)?;
+
+    let mut conn = pool.get()?;
+    for variant in &variants {
+        // Like the original, variant keys come from the checksum, so media
+        // sharing a file share its variants too.
+        let key = format!("{}-{}.{}", media.checksum, variant.name, variant.extension);
+        storage.put(&key, &variant.bytes, variant.content_type)?;
+
+        diesel::insert_into(media_variants)
+            .values(CreateMediaVariant::new(
+                media.id.clone(),
+                variant.name.clone(),
+                variant.content_type.to_string(),
+                key,
+                variant.width as i32,
+                variant.height as i32,
+                variant.bytes.len() as i64,
+            ))
+            .on_conflict_do_nothing()
+            .execute(&mut conn)?;
+    }
+
+    Ok(variants.len())
+}
@@ -0,0 +1,4 @@
+pub mod media_variants;
+pub mod render_backfill;
+pub mod scheduled_posts;
+pub mod trash_purge;
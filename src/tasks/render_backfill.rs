@@ -0,0 +1,55 @@
+use crate::{
+    db::{connection::DbPool, models::BodyFormat, schema::posts},
+    utils::render::render_body,
+};
+use actix_web::{rt, web};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+
+/// Posts rendered per query, so a large backlog doesn't load every body at once.
+const BATCH_SIZE: i64 = 100;
+
+/// Spawns a one-off task that fills in the cached HTML of posts whose
+/// `rendered_body` was cleared or never set. Reads render such posts on the
+/// fly until then, without writing.
+pub fn spawn_render_backfill(pool: DbPool) {
+    rt::spawn(async move {
+        match web::block(move || render_missing_bodies(&pool)).await {
+            Ok(Ok(0)) => {}
+            Ok(Ok(count)) => eprintln!("Rendered {} post body(ies)", count),
+            Ok(Err(e)) => eprintln!("Failed to render post bodies: {}", e),
+            Err(e) => eprintln!("Failed to render post bodies: {}", e),
+        }
+    });
+}
+
+/// Renders posts in batches. The posts trigger ignores `rendered_body`, so
+/// this leaves their `updated_at` alone.
+pub fn render_missing_bodies(
+    pool: &DbPool,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let mut rendered = 0;
+
+    loop {
+        let batch = posts::table
+            .filter(posts::rendered_body.is_null())
+            .order(posts::id.asc())
+            .limit(BATCH_SIZE)
+            .select((posts::id, posts::body, posts::body_format))
+            .load::<(String, String, BodyFormat)>(&mut conn)?;
+        if batch.is_empty() {
+            return Ok(rendered);
+        }
+
+        for (id, body, body_format) in batch {
+            // Only fills the gap; an edit that rendered the post meanwhile wins.
+            rendered += diesel::update(
+                posts::table
+                    .find(&id)
+                    .filter(posts::rendered_body.is_null()),
+            )
+            .set(posts::rendered_body.eq(render_body(&body, body_format)))
+            .execute(&mut conn)?;
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use crate::db::{connection::DbPool, models::PostStatus};
+use actix_web::{
+    rt::{self, time},
+    web,
+};
+use chrono::Utc;
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use std::time::Duration;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Spawns a background task that flips scheduled posts to published once
+/// their `published_at` has passed.
+pub fn spawn_scheduled_publisher(pool: DbPool) {
+    rt::spawn(async move {
+        let mut interval = time::interval(CHECK_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            let pool = pool.clone();
+            match web::block(move || publish_due_posts(&pool)).await {
+                Ok(Ok(0)) => {}
+                Ok(Ok(count)) => eprintln!("Published {} scheduled post(s)", count),
+                Ok(Err(e)) => eprintln!("Failed to publish scheduled posts: {}", e),
+                Err(e) => eprintln!("Failed to publish scheduled posts: {}", e),
+            }
+        }
+    });
+}
+
+pub fn publish_due_posts(pool: &DbPool) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    use crate::db::schema::posts::dsl::{deleted_at, posts, published_at, status, version};
+
+    let mut conn = pool.get()?;
+    let published = diesel::update(
+        posts
+            .filter(status.eq(PostStatus::Scheduled))
+            .filter(published_at.le(Utc::now().naive_utc()))
+            .filter(deleted_at.is_null()),
+    )
+    .set((status.eq(PostStatus::Published), version.eq(version + 1)))
+    .execute(&mut conn)?;
+
+    Ok(published)
+}
@@ -0,0 +1,46 @@
+use crate::db::connection::DbPool;
+use actix_web::{
+    rt::{self, time},
+    web,
+};
+use chrono::{TimeDelta, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use std::time::Duration;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How long trashed posts are kept when `TRASH_RETENTION_DAYS` isn't set.
+pub const DEFAULT_RETENTION_DAYS: i64 = 30;
+
+/// Spawns a background task that permanently deletes posts once they have
+/// been in the trash for longer than `retention`.
+pub fn spawn_trash_purger(pool: DbPool, retention: TimeDelta) {
+    rt::spawn(async move {
+        let mut interval = time::interval(CHECK_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            let pool = pool.clone();
+            match web::block(move || purge_expired_posts(&pool, retention)).await {
+                Ok(Ok(0)) => {}
+                Ok(Ok(count)) => eprintln!("Purged {} trashed post(s)", count),
+                Ok(Err(e)) => eprintln!("Failed to purge trashed posts: {}", e),
+                Err(e) => eprintln!("Failed to purge trashed posts: {}", e),
+            }
+        }
+    });
+}
+
+pub fn purge_expired_posts(
+    pool: &DbPool,
+    retention: TimeDelta,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    use crate::db::schema::posts::dsl::{deleted_at, posts};
+
+    let mut conn = pool.get()?;
+    let purged = diesel::delete(posts.filter(deleted_at.le(Utc::now().naive_utc() - retention)))
+        .execute(&mut conn)?;
+
+    Ok(purged)
+}
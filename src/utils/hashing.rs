@@ -4,23 +4,50 @@ use std::{
 };
 
 use argon2::{
-    password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
+    password_hash::{
+        rand_core::{OsRng, RngCore},
+        PasswordHasher, SaltString,
+    },
     Argon2, PasswordHash, PasswordVerifier,
 };
+use jsonwebtoken::errors::ErrorKind;
 use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+const JWT_ISSUER: &str = "blog-server";
+
+pub const REFRESH_TOKEN_TTL_DAYS: i64 = 7;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,
     pub exp: usize,
+    pub iat: usize,
+    pub jti: String,
+    pub iss: String,
+    pub aud: String,
+    pub method: JwtMETHODS,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
 pub enum JwtMETHODS {
-    Default,
+    EmailVerification,
     PasswordReset,
-    Login,
+    Access,
+}
+
+impl JwtMETHODS {
+    /// Each purpose gets its own audience so a token minted for one flow is
+    /// rejected by `decode_jwt` everywhere else.
+    pub fn audience(&self) -> &'static str {
+        match self {
+            JwtMETHODS::EmailVerification => "email-verification",
+            JwtMETHODS::PasswordReset => "password-reset",
+            JwtMETHODS::Access => "api",
+        }
+    }
 }
 
 pub fn hash_password(password: String) -> Result<String, argon2::password_hash::Error> {
@@ -42,40 +69,52 @@ pub fn verify_password(
     argon2.verify_password(password.as_bytes(), &parsed_hash)
 }
 
+/// Opaque refresh tokens are random bytes; only their SHA-256 digest is
+/// stored, so a database leak doesn't hand out usable tokens.
+pub fn generate_refresh_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+pub fn hash_refresh_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
 pub fn generate_jwt(
     user_id: String,
     method: JwtMETHODS,
 ) -> Result<String, jsonwebtoken::errors::Error> {
-    let expiration: u64;
+    generate_session_jwt(user_id, Uuid::new_v4().to_string(), method)
+}
+
+/// Like `generate_jwt`, but uses the given session id as the `jti` so the
+/// token can be revoked through the `sessions` table.
+pub fn generate_session_jwt(
+    user_id: String,
+    session_id: String,
+    method: JwtMETHODS,
+) -> Result<String, jsonwebtoken::errors::Error> {
     let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+    let issued_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs();
 
-    match method {
-        JwtMETHODS::Default => {
-            expiration = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_secs()
-                + 60 * 60 * 24 * 7;
-        }
-        JwtMETHODS::PasswordReset => {
-            expiration = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_secs()
-                + 60 * 60 * 24;
-        }
-        JwtMETHODS::Login => {
-            expiration = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_secs()
-                + 60 * 60;
-        }
-    }
+    let expiration = match method {
+        JwtMETHODS::EmailVerification => issued_at + 60 * 60 * 24 * 7,
+        JwtMETHODS::PasswordReset => issued_at + 60 * 60 * 24,
+        JwtMETHODS::Access => issued_at + 60 * 15,
+    };
 
     let claims = Claims {
         sub: user_id,
         exp: expiration as usize,
+        iat: issued_at as usize,
+        jti: session_id,
+        iss: JWT_ISSUER.to_string(),
+        aud: method.audience().to_string(),
+        method,
     };
 
     let token = encode(
@@ -86,12 +125,15 @@ pub fn generate_jwt(
     Ok(token)
 }
 
-pub fn decode_jwt(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+pub fn decode_jwt(token: &str, method: JwtMETHODS) -> Result<Claims, jsonwebtoken::errors::Error> {
     let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
     let mut validation = Validation::new(Algorithm::HS256);
     validation.leeway = 0;
     validation.validate_exp = true;
     validation.validate_nbf = false;
+    validation.set_issuer(&[JWT_ISSUER]);
+    validation.set_audience(&[method.audience()]);
+    validation.set_required_spec_claims(&["exp", "iat", "sub", "iss", "aud"]);
 
     let decoded_token = decode::<Claims>(
         token,
@@ -99,5 +141,9 @@ pub fn decode_jwt(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
         &validation,
     )?;
 
+    if decoded_token.claims.method != method {
+        return Err(ErrorKind::InvalidToken.into());
+    }
+
     Ok(decoded_token.claims)
 }
@@ -0,0 +1,461 @@
+use image::{
+    codecs::{jpeg::JpegEncoder, png::PngEncoder, webp::WebPEncoder},
+    imageops::FilterType,
+    metadata::Orientation,
+    DynamicImage, ImageDecoder, ImageFormat, ImageReader, ImageResult, Limits,
+};
+use std::io::Cursor;
+
+/// Larger images are refused rather than decoded, so a small file can't
+/// claim enormous dimensions and exhaust memory.
+const MAX_DECODE_DIMENSION: u32 = 12_000;
+const JPEG_QUALITY: u8 = 82;
+
+/// One configured size: images are scaled down to fit `max_dimension` on
+/// their longer side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariantSpec {
+    pub name: String,
+    pub max_dimension: u32,
+}
+
+pub struct EncodedVariant {
+    pub name: String,
+    pub content_type: &'static str,
+    pub extension: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub bytes: Vec<u8>,
+}
+
+/// Parses `name:max_dimension` pairs separated by commas, such as
+/// `thumbnail:200,medium:800,large:1600`.
+pub fn parse_variant_specs(value: &str) -> Result<Vec<VariantSpec>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (name, size) = entry
+                .split_once(':')
+                .ok_or_else(|| format!("Image variant {} must look like name:size", entry))?;
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+            {
+                return Err(format!(
+                    "Image variant name {} must be lowercase letters and digits",
+                    name
+                ));
+            }
+            let max_dimension = size
+                .parse::<u32>()
+                .ok()
+                .filter(|size| (1..=MAX_DECODE_DIMENSION).contains(size))
+                .ok_or_else(|| format!("Image variant {} has an invalid size {}", name, size))?;
+
+            Ok(VariantSpec {
+                name: name.to_string(),
+                max_dimension,
+            })
+        })
+        .collect()
+}
+
+/// Drops metadata (EXIF, XMP, IPTC, text chunks and comments) from JPEG, PNG
+/// and WebP files without re-encoding the pixels. The EXIF orientation is
+/// the exception: photos rely on it to display upright, so it is kept as a
+/// minimal EXIF block holding nothing else. Other types come back as they
+/// are; `None` means the file is too malformed to clean.
+pub fn strip_metadata(bytes: &[u8], content_type: &str) -> Option<Vec<u8>> {
+    match content_type {
+        "image/jpeg" => strip_jpeg_metadata(bytes),
+        "image/png" => strip_png_metadata(bytes),
+        "image/webp" => strip_webp_metadata(bytes),
+        _ => Some(bytes.to_vec()),
+    }
+}
+
+fn strip_jpeg_metadata(bytes: &[u8]) -> Option<Vec<u8>> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+
+    let mut stripped = vec![0xFF, 0xD8];
+    let mut i = 2;
+    loop {
+        if *bytes.get(i)? != 0xFF {
+            return None;
+        }
+        let marker = *bytes.get(i + 1)?;
+
+        match marker {
+            // Fill byte before a marker.
+            0xFF => i += 1,
+            // Start of scan: the rest is compressed image data.
+            0xDA => {
+                stripped.extend_from_slice(&bytes[i..]);
+                return Some(stripped);
+            }
+            // Markers without a length.
+            0x01 | 0xD0..=0xD7 => {
+                stripped.extend_from_slice(&bytes[i..i + 2]);
+                i += 2;
+            }
+            _ => {
+                let length = u16::from_be_bytes([*bytes.get(i + 2)?, *bytes.get(i + 3)?]) as usize;
+                let end = i + 2 + length;
+                if length < 2 || end > bytes.len() {
+                    return None;
+                }
+                let data = &bytes[i + 4..end];
+                // APP1 carries EXIF and XMP, APP13 IPTC and COM free text.
+                // APP0 (JFIF), APP2 (ICC profile) and APP14 (Adobe) affect
+                // how the image looks, so they stay.
+                if !matches!(marker, 0xE1 | 0xED | 0xFE) {
+                    stripped.extend_from_slice(&bytes[i..end]);
+                } else if let Some(exif) = data.strip_prefix(EXIF_HEADER).and_then(orientation_exif)
+                {
+                    let length = u16::try_from(2 + EXIF_HEADER.len() + exif.len()).ok()?;
+                    stripped.extend_from_slice(&[0xFF, 0xE1]);
+                    stripped.extend_from_slice(&length.to_be_bytes());
+                    stripped.extend_from_slice(EXIF_HEADER);
+                    stripped.extend_from_slice(&exif);
+                }
+                i = end;
+            }
+        }
+    }
+}
+
+fn strip_png_metadata(bytes: &[u8]) -> Option<Vec<u8>> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if !bytes.starts_with(SIGNATURE) {
+        return None;
+    }
+
+    let mut stripped = SIGNATURE.to_vec();
+    let mut i = SIGNATURE.len();
+    loop {
+        let length = u32::from_be_bytes(bytes.get(i..i + 4)?.try_into().ok()?) as usize;
+        let chunk_type = bytes.get(i + 4..i + 8)?;
+        // Length, type, data and CRC.
+        let end = i.checked_add(12)?.checked_add(length)?;
+        let chunk = bytes.get(i..end)?;
+
+        if !matches!(chunk_type, b"eXIf" | b"tEXt" | b"zTXt" | b"iTXt" | b"tIME") {
+            stripped.extend_from_slice(chunk);
+        } else if let Some(exif) = (chunk_type == b"eXIf")
+            .then(|| orientation_exif(&chunk[8..8 + length]))
+            .flatten()
+        {
+            let mut exif_chunk = u32::try_from(exif.len()).ok()?.to_be_bytes().to_vec();
+            exif_chunk.extend_from_slice(b"eXIf");
+            exif_chunk.extend_from_slice(&exif);
+            let crc = crc32(&exif_chunk[4..]);
+            exif_chunk.extend_from_slice(&crc.to_be_bytes());
+            stripped.extend_from_slice(&exif_chunk);
+        }
+        if chunk_type == b"IEND" {
+            return Some(stripped);
+        }
+        i = end;
+    }
+}
+
+fn strip_webp_metadata(bytes: &[u8]) -> Option<Vec<u8>> {
+    if bytes.len() < 12 || !bytes.starts_with(b"RIFF") || &bytes[8..12] != b"WEBP" {
+        return None;
+    }
+
+    let mut stripped = bytes[..12].to_vec();
+    let mut kept_exif = false;
+    let mut i = 12;
+    while i < bytes.len() {
+        let fourcc = bytes.get(i..i + 4)?;
+        let size = u32::from_le_bytes(bytes.get(i + 4..i + 8)?.try_into().ok()?) as usize;
+        // Chunks are padded to an even length.
+        let end = (i + 8).checked_add(size + size % 2)?.min(bytes.len());
+        let chunk = bytes.get(i..end)?;
+
+        match fourcc {
+            b"EXIF" => {
+                let data = chunk.get(8..8 + size)?;
+                if let Some(exif) = orientation_exif(data.strip_prefix(EXIF_HEADER).unwrap_or(data))
+                {
+                    stripped.extend_from_slice(b"EXIF");
+                    stripped.extend_from_slice(&u32::try_from(exif.len()).ok()?.to_le_bytes());
+                    stripped.extend_from_slice(&exif);
+                    kept_exif = true;
+                }
+            }
+            b"XMP " => {}
+            b"VP8X" => {
+                let mut chunk = chunk.to_vec();
+                // Clear the flags announcing the dropped chunks; EXIF is
+                // announced again below if the orientation was kept.
+                *chunk.get_mut(8)? &= !(0x08 | 0x04);
+                stripped.extend_from_slice(&chunk);
+            }
+            _ => stripped.extend_from_slice(chunk),
+        }
+        i = end;
+    }
+
+    if kept_exif && stripped.get(12..16) == Some(b"VP8X") {
+        stripped[20] |= 0x08;
+    }
+
+    let riff_size = u32::try_from(stripped.len() - 8).ok()?;
+    stripped[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    Some(stripped)
+}
+
+/// Prefix of the EXIF data in JPEG APP1 segments (and some WebP files).
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+
+/// A TIFF block with only the orientation from `exif`, or `None` when the
+/// image is already upright (or the orientation can't be read) and the EXIF
+/// can go entirely.
+fn orientation_exif(exif: &[u8]) -> Option<Vec<u8>> {
+    let orientation = Orientation::from_exif_chunk(exif)?;
+    if orientation == Orientation::NoTransforms {
+        return None;
+    }
+
+    // Big-endian header, then one IFD with a single SHORT entry (tag 0x0112)
+    // whose value is padded to four bytes, and no next IFD.
+    let mut tiff = b"MM\0\x2a\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01\0".to_vec();
+    tiff.extend_from_slice(&[orientation.to_exif(), 0, 0, 0, 0, 0, 0]);
+    Some(tiff)
+}
+
+/// The CRC-32 PNG chunks end with, over their type and data.
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ u32::from(byte), |crc, _| {
+            (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+/// The width and height the image displays at, with a quarter-turn EXIF
+/// orientation swapping them. `None` means the image can't be read.
+pub fn display_size(bytes: &[u8]) -> Option<(u32, u32)> {
+    let size = imagesize::blob_size(bytes).ok()?;
+    let (width, height) = (
+        u32::try_from(size.width).ok()?,
+        u32::try_from(size.height).ok()?,
+    );
+
+    let orientation = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()
+        .and_then(|reader| reader.into_decoder().ok())
+        .and_then(|mut decoder| decoder.orientation().ok());
+    match orientation {
+        Some(
+            Orientation::Rotate90
+            | Orientation::Rotate270
+            | Orientation::Rotate90FlipH
+            | Orientation::Rotate270FlipH,
+        ) => Some((height, width)),
+        _ => Some((width, height)),
+    }
+}
+
+/// Decodes the image, applies its EXIF orientation and renders every spec
+/// twice: in the original's format family (JPEG for JPEG, PNG otherwise) and
+/// as lossless WebP, the only WebP the encoder writes. Images already within
+/// a spec's size are re-encoded, not enlarged.
+pub fn render_variants(bytes: &[u8], specs: &[VariantSpec]) -> ImageResult<Vec<EncodedVariant>> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DECODE_DIMENSION);
+    limits.max_image_height = Some(MAX_DECODE_DIMENSION);
+
+    let mut reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
+    reader.limits(limits);
+    let source_format = reader.format();
+
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+
+    let mut variants = Vec::with_capacity(specs.len() * 2);
+    for spec in specs {
+        let resized = if image.width().max(image.height()) > spec.max_dimension {
+            image.resize(spec.max_dimension, spec.max_dimension, FilterType::Lanczos3)
+        } else {
+            image.clone()
+        };
+
+        let (content_type, extension, bytes) = if source_format == Some(ImageFormat::Jpeg) {
+            let mut bytes = Vec::new();
+            DynamicImage::ImageRgb8(resized.to_rgb8())
+                .write_with_encoder(JpegEncoder::new_with_quality(&mut bytes, JPEG_QUALITY))?;
+            ("image/jpeg", "jpg", bytes)
+        } else {
+            let mut bytes = Vec::new();
+            resized.write_with_encoder(PngEncoder::new(&mut bytes))?;
+            ("image/png", "png", bytes)
+        };
+        variants.push(EncodedVariant {
+            name: spec.name.clone(),
+            content_type,
+            extension,
+            width: resized.width(),
+            height: resized.height(),
+            bytes,
+        });
+
+        // The WebP encoder only takes 8-bit RGB or RGBA.
+        let webp_source = if resized.color().has_alpha() {
+            DynamicImage::ImageRgba8(resized.to_rgba8())
+        } else {
+            DynamicImage::ImageRgb8(resized.to_rgb8())
+        };
+        let mut bytes = Vec::new();
+        webp_source.write_with_encoder(WebPEncoder::new_lossless(&mut bytes))?;
+        variants.push(EncodedVariant {
+            name: spec.name.clone(),
+            content_type: "image/webp",
+            extension: "webp",
+            width: resized.width(),
+            height: resized.height(),
+            bytes,
+        });
+    }
+
+    Ok(variants)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    /// A 16x8 photo, red on the left and blue on the right, tagged with EXIF
+    /// orientation 6 (rotate 90° clockwise) like a phone held upright, plus
+    /// a camera make and a comment that should not survive.
+    fn rotated_jpeg() -> Vec<u8> {
+        let photo = RgbImage::from_fn(16, 8, |x, _| {
+            if x < 8 {
+                Rgb([255, 0, 0])
+            } else {
+                Rgb([0, 0, 255])
+            }
+        });
+        let mut encoded = Vec::new();
+        DynamicImage::ImageRgb8(photo)
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut encoded, 95))
+            .unwrap();
+
+        // Little-endian TIFF with Make = "ABC" and Orientation = 6.
+        let mut exif = EXIF_HEADER.to_vec();
+        exif.extend_from_slice(b"II\x2a\0\x08\0\0\0\x02\0");
+        exif.extend_from_slice(b"\x0f\x01\x02\0\x04\0\0\0ABC\0");
+        exif.extend_from_slice(b"\x12\x01\x03\0\x01\0\0\0\x06\0\0\0");
+        exif.extend_from_slice(b"\0\0\0\0");
+        let comment = b"secret comment";
+
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        jpeg.extend_from_slice(&(exif.len() as u16 + 2).to_be_bytes());
+        jpeg.extend_from_slice(&exif);
+        jpeg.extend_from_slice(&[0xFF, 0xFE]);
+        jpeg.extend_from_slice(&(comment.len() as u16 + 2).to_be_bytes());
+        jpeg.extend_from_slice(comment);
+        jpeg.extend_from_slice(&encoded[2..]);
+        jpeg
+    }
+
+    fn orientation(bytes: &[u8]) -> Orientation {
+        ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()
+            .unwrap()
+            .into_decoder()
+            .unwrap()
+            .orientation()
+            .unwrap()
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+    }
+
+    #[test]
+    fn keeps_only_the_orientation_of_a_rotated_jpeg() {
+        let stripped = strip_metadata(&rotated_jpeg(), "image/jpeg").unwrap();
+
+        assert!(!contains(&stripped, b"secret comment"));
+        assert!(!contains(&stripped, b"ABC\0"));
+        assert_eq!(orientation(&stripped), Orientation::Rotate90);
+        assert_eq!(display_size(&stripped), Some((8, 16)));
+    }
+
+    #[test]
+    fn renders_variants_of_a_rotated_jpeg_upright() {
+        let stripped = strip_metadata(&rotated_jpeg(), "image/jpeg").unwrap();
+        let specs = [VariantSpec {
+            name: "small".into(),
+            max_dimension: 100,
+        }];
+
+        let variants = render_variants(&stripped, &specs).unwrap();
+
+        assert_eq!(variants.len(), 2);
+        for variant in variants {
+            assert_eq!((variant.width, variant.height), (8, 16));
+            let image = image::load_from_memory(&variant.bytes).unwrap().to_rgb8();
+            // Rotated clockwise, the red left half is now on top.
+            assert!(image.get_pixel(4, 2)[0] > 200);
+            assert!(image.get_pixel(4, 13)[2] > 200);
+        }
+    }
+
+    #[test]
+    fn drops_the_exif_of_an_upright_jpeg() {
+        let mut jpeg = rotated_jpeg();
+        let at = jpeg
+            .windows(2)
+            .position(|window| window == b"\x06\0")
+            .unwrap();
+        jpeg[at] = 1;
+
+        let stripped = strip_metadata(&jpeg, "image/jpeg").unwrap();
+
+        assert!(!contains(&stripped, EXIF_HEADER));
+        assert_eq!(display_size(&stripped), Some((16, 8)));
+    }
+
+    #[test]
+    fn keeps_the_orientation_of_a_png_with_a_valid_chunk() {
+        let mut png = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(16, 8))
+            .write_with_encoder(PngEncoder::new(&mut png))
+            .unwrap();
+        // Insert an eXIf chunk with orientation 8 right after IHDR.
+        let exif =
+            orientation_exif(b"MM\0\x2a\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01\0\x08\0\0\0\0\0\0")
+                .unwrap();
+        let mut chunk = (exif.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(b"eXIf");
+        chunk.extend_from_slice(&exif);
+        chunk.extend_from_slice(&crc32(&chunk[4..]).to_be_bytes());
+        png.splice(33..33, chunk);
+
+        let stripped = strip_metadata(&png, "image/png").unwrap();
+
+        assert!(image::load_from_memory(&stripped).is_ok());
+        assert_eq!(orientation(&stripped), Orientation::Rotate270);
+        assert_eq!(display_size(&stripped), Some((8, 16)));
+    }
+
+    #[test]
+    fn computes_the_png_crc() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+    }
+}
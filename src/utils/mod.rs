@@ -1 +1,5 @@
 pub mod hashing;
+pub mod images;
+pub mod render;
+pub mod site;
+pub mod slug;
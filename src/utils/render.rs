@@ -0,0 +1,202 @@
+use crate::{db::models::BodyFormat, utils::slug::slugify};
+use ammonia::Builder;
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag, TagEnd};
+use std::{borrow::Cow, collections::HashSet};
+
+/// Renders a post body to sanitized HTML. Markdown and HTML bodies go through
+/// the same allowlist, so stored output is safe to embed as-is.
+pub fn render_body(body: &str, format: BodyFormat) -> String {
+    match format {
+        BodyFormat::Markdown => sanitizer().clean(&markdown_to_html(body)).to_string(),
+        BodyFormat::Html => sanitizer().clean(body).to_string(),
+        BodyFormat::Plain => plain_to_html(body),
+    }
+}
+
+fn markdown_to_html(body: &str) -> String {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_HEADING_ATTRIBUTES;
+
+    let mut output = String::new();
+    html::push_html(
+        &mut output,
+        with_heading_anchors(Parser::new_ext(body, options)),
+    );
+    output
+}
+
+/// Gives every heading without an explicit `{#id}` an id derived from its
+/// text, numbering repeats so anchors stay unique within the post. Explicit
+/// ids are reserved first, so a generated one never collides with them.
+fn with_heading_anchors<'a>(parser: Parser<'a>) -> impl Iterator<Item = Event<'a>> {
+    let events: Vec<Event<'a>> = parser.collect();
+    let mut taken: HashSet<String> = events
+        .iter()
+        .filter_map(|event| match event {
+            Event::Start(Tag::Heading { id: Some(id), .. }) => Some(id.to_string()),
+            _ => None,
+        })
+        .collect();
+
+    let mut anchored = Vec::with_capacity(events.len());
+    let mut events = events.into_iter();
+    while let Some(event) = events.next() {
+        let Event::Start(Tag::Heading {
+            level,
+            id,
+            classes,
+            attrs,
+        }) = event
+        else {
+            anchored.push(event);
+            continue;
+        };
+
+        let mut inner = Vec::new();
+        let mut text = String::new();
+        for event in events.by_ref() {
+            match &event {
+                Event::End(TagEnd::Heading(_)) => {
+                    inner.push(event);
+                    break;
+                }
+                Event::Text(value) | Event::Code(value) => text.push_str(value),
+                _ => {}
+            }
+            inner.push(event);
+        }
+
+        let id = id.or_else(|| {
+            let base = slugify(&text);
+            let base = if base.is_empty() {
+                "section".into()
+            } else {
+                base
+            };
+            let id = (1..)
+                .map(|n| match n {
+                    1 => base.clone(),
+                    n => format!("{}-{}", base, n),
+                })
+                .find(|candidate| !taken.contains(candidate))?;
+            taken.insert(id.clone());
+
+            Some(CowStr::from(id))
+        });
+
+        anchored.push(Event::Start(Tag::Heading {
+            level,
+            id,
+            classes,
+            attrs,
+        }));
+        anchored.extend(inner);
+    }
+
+    anchored.into_iter()
+}
+
+/// Prepended to every id in rendered bodies, and to the fragment links that
+/// point at them, so a post can't claim ids the surrounding page relies on.
+const ID_PREFIX: &str = "user-content-";
+
+fn sanitizer() -> Builder<'static> {
+    let mut builder = Builder::default();
+    builder
+        .add_tag_attributes("h1", &["id"])
+        .add_tag_attributes("h2", &["id"])
+        .add_tag_attributes("h3", &["id"])
+        .add_tag_attributes("h4", &["id"])
+        .add_tag_attributes("h5", &["id"])
+        .add_tag_attributes("h6", &["id"])
+        .add_tag_attributes("code", &["class"])
+        .add_tag_attributes("sup", &["class"])
+        .add_tag_attributes("div", &["class", "id"])
+        .attribute_filter(|element, attribute, value| match (element, attribute) {
+            // Only the classes the markdown renderer emits survive.
+            ("code", "class") if value.starts_with("language-") => Some(Cow::Borrowed(value)),
+            ("sup" | "div", "class") if value.starts_with("footnote-") => {
+                Some(Cow::Borrowed(value))
+            }
+            (_, "class") => None,
+            (_, "id") => Some(Cow::Owned(format!("{}{}", ID_PREFIX, value))),
+            ("a", "href") if value.starts_with('#') => {
+                Some(Cow::Owned(format!("#{}{}", ID_PREFIX, &value[1..])))
+            }
+            _ => Some(Cow::Borrowed(value)),
+        });
+    builder
+}
+
+/// Escapes the text and turns blank-line separated blocks into paragraphs.
+fn plain_to_html(body: &str) -> String {
+    body.replace("\r\n", "\n")
+        .split("\n\n")
+        .map(str::trim)
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| format!("<p>{}</p>", escape_html(paragraph).replace('\n', "<br>\n")))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefixes_heading_ids_and_their_links() {
+        let html = render_body(
+            "# Intro {#login-form}\n\n## Intro\n\n[back](#login-form)",
+            BodyFormat::Markdown,
+        );
+
+        assert!(html.contains(r#"<h1 id="user-content-login-form">"#));
+        assert!(html.contains(r#"<h2 id="user-content-intro">"#));
+        assert!(html.contains(r##"<a href="#user-content-login-form""##));
+        assert!(!html.contains(r#"id="login-form""#));
+    }
+
+    #[test]
+    fn numbers_generated_ids_past_explicit_ones() {
+        let html = render_body(
+            "# Setup\n\n# Other {#setup}\n\n# Setup",
+            BodyFormat::Markdown,
+        );
+
+        assert!(html.contains(r#"<h1 id="user-content-setup-2">Setup</h1>"#));
+        assert!(html.contains(r#"<h1 id="user-content-setup">Other</h1>"#));
+        assert!(html.contains(r#"<h1 id="user-content-setup-3">Setup</h1>"#));
+    }
+
+    #[test]
+    fn prefixes_ids_in_html_bodies() {
+        let html = render_body(r#"<div id="__proto__">x</div>"#, BodyFormat::Html);
+
+        assert_eq!(html, r#"<div id="user-content-__proto__">x</div>"#);
+    }
+
+    #[test]
+    fn keeps_footnote_links_pointing_at_their_definitions() {
+        let html = render_body("Claim[^1]\n\n[^1]: Source", BodyFormat::Markdown);
+
+        assert!(html.contains(r##"href="#user-content-1""##));
+        assert!(html.contains(r#"id="user-content-1""#));
+    }
+}
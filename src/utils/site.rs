@@ -0,0 +1,31 @@
+use std::env;
+
+const DEFAULT_SITE_URL: &str = "https://rishabhportfolio.site";
+const DEFAULT_SITE_TITLE: &str = "Blog";
+const DEFAULT_API_URL: &str = "http://localhost:5000";
+
+/// The public site's base URL, from `SITE_URL`, without a trailing slash.
+pub fn site_url() -> String {
+    env::var("SITE_URL")
+        .map(|url| url.trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| DEFAULT_SITE_URL.into())
+}
+
+/// Where this server is publicly reached, from `API_URL`, without a trailing
+/// slash. Absolute links to the server's own routes use it instead of the
+/// request's Host header, which clients control and caches don't key on.
+pub fn api_url() -> String {
+    env::var("API_URL")
+        .map(|url| url.trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| DEFAULT_API_URL.into())
+}
+
+/// The site's name, from `SITE_TITLE`.
+pub fn site_title() -> String {
+    env::var("SITE_TITLE").unwrap_or_else(|_| DEFAULT_SITE_TITLE.into())
+}
+
+/// Where readers find a post on the public site.
+pub fn post_url(slug: &str) -> String {
+    format!("{}/posts/{}", site_url(), slug)
+}
@@ -0,0 +1,24 @@
+const MAX_SLUG_LENGTH: usize = 80;
+
+/// Lowercases `value` and joins its ASCII alphanumeric runs with dashes, e.g.
+/// "Hello, World!" becomes "hello-world", cut to 80 characters. The
+/// `add_slugs_to_posts` migration backfilled existing posts the same way.
+pub fn slugify(value: &str) -> String {
+    let mut slug = String::new();
+    let mut pending_dash = false;
+
+    for c in value.chars() {
+        if c.is_ascii_alphanumeric() {
+            if pending_dash && !slug.is_empty() {
+                slug.push('-');
+            }
+            pending_dash = false;
+            slug.push(c.to_ascii_lowercase());
+        } else {
+            pending_dash = true;
+        }
+    }
+
+    slug.truncate(MAX_SLUG_LENGTH);
+    slug.trim_end_matches('-').to_string()
+}